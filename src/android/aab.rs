@@ -4,7 +4,7 @@ use colored::Colorize;
 use heck::ToUpperCamelCase;
 use thiserror::Error;
 
-use super::{config::Config, env::Env, target::Target};
+use super::{config::Config, env::Env, symbols, target::Target};
 use crate::{
     opts::{NoiseLevel, Profile},
     util::{
@@ -17,17 +17,21 @@ use crate::{
 pub enum AabError {
     #[error("Failed to build AAB: {0}")]
     BuildFailed(#[from] std::io::Error),
+    #[error(transparent)]
+    SymbolsFailed(symbols::Error),
 }
 
 impl Reportable for AabError {
     fn report(&self) -> Report {
         match self {
             Self::BuildFailed(err) => Report::error("Failed to build AAB", err),
+            Self::SymbolsFailed(err) => err.report(),
         }
     }
 }
 
-/// Builds AAB(s) and returns the built AAB(s) paths
+/// Builds AAB(s) and returns the built AAB(s) paths. Release builds also get
+/// a [`symbols::ARCHIVE_NAME`] next to each AAB.
 pub fn build(
     config: &Config,
     env: &Env,
@@ -89,13 +93,21 @@ pub fn build(
 
     let mut outputs = Vec::new();
     if split_per_abi {
-        outputs.extend(
-            targets
-                .iter()
-                .map(|t| dunce::simplified(&aab_path(config, profile, t.arch)).to_path_buf()),
-        );
+        for target in &targets {
+            let output = dunce::simplified(&aab_path(config, profile, target.arch)).to_path_buf();
+            if profile.release() {
+                symbols::write_archive(config, profile, &[target], &symbols::archive_path(&output))
+                    .map_err(AabError::SymbolsFailed)?;
+            }
+            outputs.push(output);
+        }
     } else {
-        outputs.push(dunce::simplified(&aab_path(config, profile, "universal")).to_path_buf());
+        let output = dunce::simplified(&aab_path(config, profile, "universal")).to_path_buf();
+        if profile.release() {
+            symbols::write_archive(config, profile, &targets, &symbols::archive_path(&output))
+                .map_err(AabError::SymbolsFailed)?;
+        }
+        outputs.push(output);
     }
 
     Ok(outputs)
@@ -142,6 +154,12 @@ pub mod cli {
         for p in &outputs {
             println!("    {}", p.to_string_lossy().green(),);
         }
+        if profile.release() {
+            println!("\nNative debug symbols for uploading to the Play Console:");
+            for p in &outputs {
+                println!("    {}", symbols::archive_path(p).to_string_lossy().green());
+            }
+        }
         Ok(())
     }
 }
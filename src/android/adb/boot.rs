@@ -0,0 +1,120 @@
+use super::{adb, get_prop};
+use crate::{
+    android::env::Env,
+    util::cli::{Report, Reportable},
+};
+use std::{
+    fmt::{self, Display},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a device has to get through before it's ready to install apps on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage {
+    /// Showing up as `device` (rather than `offline`) in adb.
+    Connected,
+    /// `sys.boot_completed` and `dev.bootcomplete` being set.
+    BootCompleted,
+    /// The package manager answering queries.
+    PackageManager,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connected => write!(f, "the device to connect"),
+            Self::BootCompleted => write!(f, "boot to complete"),
+            Self::PackageManager => write!(f, "the package manager to start"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{serial_no} still wasn't ready after {}s (waiting for {stage})", timeout.as_secs())]
+    TimedOut {
+        serial_no: String,
+        timeout: Duration,
+        stage: Stage,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::TimedOut { .. } => Report::error("Device didn't finish booting", self),
+        }
+    }
+}
+
+fn shell_output(env: &Env, serial_no: &str, args: &'static [&'static str]) -> Option<String> {
+    adb(env, serial_no)
+        .before_spawn(move |cmd| {
+            cmd.args(args);
+            Ok(())
+        })
+        .stderr_null()
+        .read()
+        .ok()
+}
+
+fn reached(env: &Env, serial_no: &str, stage: Stage) -> bool {
+    match stage {
+        Stage::Connected => {
+            shell_output(env, serial_no, &["get-state"]).as_deref() == Some("device")
+        }
+        Stage::BootCompleted => {
+            let prop = |name| get_prop(env, serial_no, name).ok();
+            // Not every device sets `dev.bootcomplete`, but those that do set
+            // it later than `sys.boot_completed`.
+            prop("sys.boot_completed").as_deref() == Some("1")
+                && matches!(prop("dev.bootcomplete").as_deref(), Some("1") | Some(""))
+        }
+        Stage::PackageManager => shell_output(env, serial_no, &["shell", "pm", "path", "android"])
+            .is_some_and(|output| output.trim_start().starts_with("package:")),
+    }
+}
+
+/// Blocks until a device is fully booted and ready to have apps installed,
+/// which works the same for emulators (local or over TCP) and physical
+/// devices that were just rebooted. Returns immediately for devices that are
+/// already up.
+pub fn wait_for_boot(env: &Env, serial_no: &str, timeout: Duration) -> Result<(), Error> {
+    wait_for_boot_while(env, serial_no, timeout, || true).map(|_| ())
+}
+
+/// Like [`wait_for_boot`], but gives up as soon as `alive` returns `false`
+/// (i.e. because the emulator process exited), returning whether the device
+/// booted.
+pub fn wait_for_boot_while(
+    env: &Env,
+    serial_no: &str,
+    timeout: Duration,
+    mut alive: impl FnMut() -> bool,
+) -> Result<bool, Error> {
+    let start = Instant::now();
+    for stage in [
+        Stage::Connected,
+        Stage::BootCompleted,
+        Stage::PackageManager,
+    ] {
+        while !reached(env, serial_no, stage) {
+            if !alive() {
+                return Ok(false);
+            }
+            if start.elapsed() >= timeout {
+                return Err(Error::TimedOut {
+                    serial_no: serial_no.to_owned(),
+                    timeout,
+                    stage,
+                });
+            }
+            sleep(POLL_INTERVAL);
+        }
+    }
+    Ok(true)
+}
@@ -4,7 +4,7 @@ use crate::{
     env::ExplicitEnv as _,
     util::cli::{Report, Reportable},
 };
-use once_cell_regex::regex_multi_line;
+use once_cell_regex::{exports::regex::Regex, regex_multi_line};
 use std::{collections::BTreeSet, process::Command};
 use thiserror::Error;
 
@@ -38,30 +38,47 @@ impl Reportable for Error {
 }
 
 const ADB_DEVICE_REGEX: &str = r"^([\S]{6,100})	device\b";
+// Matches devices in any state, i.e. `offline` while an emulator boots
+const ADB_ANY_STATE_REGEX: &str = r"^([\S]{6,100})	\S+";
 
-pub fn device_list(env: &Env) -> Result<BTreeSet<Device<'static>>, Error> {
+fn serials(env: &Env, regex: &Regex) -> Result<Vec<String>, Error> {
     let mut cmd = Command::new(env.platform_tools_path().join("adb"));
     cmd.arg("devices").envs(env.explicit_env());
 
     super::check_authorized(&cmd.output()?)
         .map(|raw_list| {
-            regex_multi_line!(ADB_DEVICE_REGEX)
+            regex
                 .captures_iter(&raw_list)
-                .map(|caps| {
-                    assert_eq!(caps.len(), 2);
-                    let serial_no = caps.get(1).unwrap().as_str().to_owned();
-                    let model = get_prop(env, &serial_no, "ro.product.model")
-                        .map_err(Error::ModelFailed)?;
-                    let name = device_name(env, &serial_no).unwrap_or_else(|_| model.clone());
-                    let abi = get_prop(env, &serial_no, "ro.product.cpu.abi")
-                        .map_err(Error::AbiFailed)?;
-                    let target =
-                        Target::for_abi(&abi).ok_or_else(|| Error::AbiInvalid(abi.clone()))?;
-                    Ok(Device::new(serial_no, name, model, target))
-                })
+                .map(|caps| caps.get(1).unwrap().as_str().to_owned())
                 .collect()
         })
-        .map_err(Error::DevicesFailed)?
+        .map_err(Error::DevicesFailed)
+}
+
+/// Lists the serials of connected devices, without querying the devices
+/// themselves.
+pub fn serial_list(env: &Env) -> Result<Vec<String>, Error> {
+    serials(env, regex_multi_line!(ADB_DEVICE_REGEX))
+}
+
+/// Like [`serial_list`], but also includes devices that aren't ready yet,
+/// i.e. booting emulators.
+pub fn serial_list_any_state(env: &Env) -> Result<Vec<String>, Error> {
+    serials(env, regex_multi_line!(ADB_ANY_STATE_REGEX))
+}
+
+pub fn device_list(env: &Env) -> Result<BTreeSet<Device<'static>>, Error> {
+    serial_list(env)?
+        .into_iter()
+        .map(|serial_no| {
+            let model =
+                get_prop(env, &serial_no, "ro.product.model").map_err(Error::ModelFailed)?;
+            let name = device_name(env, &serial_no).unwrap_or_else(|_| model.clone());
+            let abi = get_prop(env, &serial_no, "ro.product.cpu.abi").map_err(Error::AbiFailed)?;
+            let target = Target::for_abi(&abi).ok_or_else(|| Error::AbiInvalid(abi.clone()))?;
+            Ok(Device::new(serial_no, name, model, target))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -89,7 +106,23 @@ mod test {
 
     )]
     fn test_adb_output_regex(input: &str, devices: Vec<&'static str>) {
-        let regex = regex_multi_line!(ADB_DEVICE_REGEX);
+        assert_captures(regex_multi_line!(ADB_DEVICE_REGEX), input, devices);
+    }
+
+    #[rstest(input, devices,
+        case("List of devices attached\n\
+            emulator-5554\toffline\n\
+            emulator-5556\tdevice\n\
+            AB1234DEFG\tunauthorized usb:1-1 transport_id:3\n\
+            ", vec!["emulator-5554", "emulator-5556", "AB1234DEFG"]
+        ),
+        case("List of devices attached \n", vec![]),
+    )]
+    fn test_adb_any_state_regex(input: &str, devices: Vec<&'static str>) {
+        assert_captures(regex_multi_line!(ADB_ANY_STATE_REGEX), input, devices);
+    }
+
+    fn assert_captures(regex: &Regex, input: &str, devices: Vec<&'static str>) {
         println!("{}", input);
         let captures = regex
             .captures_iter(input)
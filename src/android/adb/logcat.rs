@@ -0,0 +1,641 @@
+use super::{
+    adb,
+    logcat_parser::{self, Entry, Filter, Level, Parser},
+};
+use crate::{
+    android::env::Env,
+    util::cli::{Report, Reportable},
+    DuctExpressionExt,
+};
+use colored::Colorize as _;
+use once_cell_regex::exports::regex::Regex;
+use serde::Serialize;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead as _, BufReader, Write as _},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, sleep, JoinHandle},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+pub const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to run `adb shell pidof`: {0}")]
+    PidofFailed(std::io::Error),
+    #[error("Timed out after {}s waiting for {package} to start", timeout.as_secs())]
+    StartTimedOut { package: String, timeout: Duration },
+    #[error("Failed to run `adb logcat`: {0}")]
+    LogcatFailed(std::io::Error),
+    #[error("Log streaming thread panicked")]
+    ThreadPanicked,
+    #[error("Failed to open log file {path:?}: {source}")]
+    OutputFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::PidofFailed(err) => Report::error("Failed to run `adb shell pidof`", err),
+            Self::StartTimedOut { .. } => Report::error("App didn't start", self),
+            Self::LogcatFailed(err) => Report::error("Failed to run `adb logcat`", err),
+            Self::ThreadPanicked => Report::error("Failed to stream device logs", self),
+            Self::OutputFailed { path, source } => {
+                Report::error(format!("Failed to open log file {:?}", path), source)
+            }
+        }
+    }
+}
+
+/// Returns the pid of the running process for `package`, if there is one.
+pub fn pidof(env: &Env, serial_no: &str, package: &str) -> Result<Option<u32>, Error> {
+    let package = package.to_owned();
+    let output = adb(env, serial_no)
+        .before_spawn(move |cmd| {
+            cmd.args(["shell", "pidof", "-s", &package]);
+            Ok(())
+        })
+        .stderr_capture()
+        .stdout_capture()
+        .unchecked()
+        .run()
+        .map_err(Error::PidofFailed)?;
+    // `pidof` exits with 1 when nothing matched
+    Ok(output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+        .flatten())
+}
+
+/// Whether the crash buffer holds a crash report for `pid`, which tells a
+/// crash apart from the app exiting normally.
+fn crashed(env: &Env, serial_no: &str, pid: u32) -> bool {
+    adb(env, serial_no)
+        .before_spawn(|cmd| {
+            cmd.args(["logcat", "-d", "-b", "crash", "-v", "threadtime"]);
+            Ok(())
+        })
+        .stderr_null()
+        .unchecked()
+        .read()
+        .map(|crash_log| {
+            let native = format!("pid: {},", pid);
+            logcat_parser::parse(&crash_log)
+                .iter()
+                .any(|entry| match entry {
+                    Entry::Record(record) => record.pid == pid || record.message.contains(&native),
+                    Entry::Unparsed { unparsed } => unparsed.contains(&native),
+                })
+        })
+        .unwrap_or(false)
+}
+
+/// How logs are printed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Format {
+    /// `adb logcat`'s own colored output, passed through untouched.
+    #[default]
+    Raw,
+    /// Parsed and filtered in Rust, then printed one record per line.
+    Text,
+    /// Parsed and filtered in Rust, then printed as newline-delimited JSON.
+    Json,
+}
+
+/// Log filtering and output options, as given on the command line.
+#[derive(Clone, Debug, Default)]
+pub struct LogOptions {
+    pub tags: Vec<String>,
+    pub pattern: Option<Regex>,
+    pub pids: Vec<u32>,
+    pub json: bool,
+    pub output: Option<OutputFile>,
+}
+
+impl LogOptions {
+    pub fn format(&self) -> Format {
+        if self.json {
+            Format::Json
+        } else if !self.tags.is_empty()
+            || self.pattern.is_some()
+            || !self.pids.is_empty()
+            || self.output.is_some()
+        {
+            Format::Text
+        } else {
+            Format::Raw
+        }
+    }
+}
+
+/// Where to record a copy of the logs, and when to rotate it.
+#[derive(Clone, Debug)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    /// Once the file would grow past this many bytes, it's moved to
+    /// `<path>.1` (bumping older files along) and a new one is started.
+    pub max_size: u64,
+    /// How many rotated files to keep around.
+    pub max_files: usize,
+}
+
+impl OutputFile {
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+}
+
+#[derive(Debug)]
+struct LogFile {
+    options: OutputFile,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(options: &OutputFile) -> io::Result<Self> {
+        if let Some(parent) = options.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&options.path)?;
+        Ok(Self {
+            size: file.metadata()?.len(),
+            options: options.clone(),
+            file,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+            if from.exists() {
+                if to.exists() {
+                    fs::remove_file(to)?;
+                }
+                fs::rename(from, to)?;
+            }
+            Ok(())
+        }
+
+        let max_files = self.options.max_files;
+        if max_files > 0 {
+            for index in (1..max_files).rev() {
+                rename_if_exists(
+                    &self.options.rotated_path(index),
+                    &self.options.rotated_path(index + 1),
+                )?;
+            }
+            rename_if_exists(&self.options.path, &self.options.rotated_path(1))?;
+        }
+        self.file = File::create(&self.options.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.options.max_size {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    serial: &'a str,
+    #[serde(flatten)]
+    entry: &'a Entry,
+}
+
+/// Follows the logs of an app on a single device.
+///
+/// Streaming starts once the app's process is up, and picks up the new
+/// process if the app is restarted.
+#[derive(Clone, Debug)]
+pub struct Logcat {
+    serial_no: String,
+    package: String,
+    args: Vec<String>,
+    startup_timeout: Duration,
+    prefix: Option<String>,
+    format: Format,
+    filter: Filter,
+    output: Option<OutputFile>,
+    log_file: Arc<Mutex<Option<LogFile>>>,
+}
+
+impl Logcat {
+    pub fn new(serial_no: impl Into<String>, package: impl Into<String>) -> Self {
+        Self {
+            serial_no: serial_no.into(),
+            package: package.into(),
+            args: Vec::new(),
+            startup_timeout: DEFAULT_STARTUP_TIMEOUT,
+            prefix: None,
+            format: Format::default(),
+            filter: Filter::default(),
+            output: None,
+            log_file: Default::default(),
+        }
+    }
+
+    /// Extra arguments passed to `adb logcat` (i.e. filter specs).
+    pub fn with_args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// How long to wait for the app's process to appear, both initially and
+    /// after it exits.
+    pub fn with_startup_timeout(mut self, startup_timeout: Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+
+    /// Prefixes every log line, which makes logs from several devices
+    /// distinguishable.
+    pub fn with_prefix(mut self, prefix: Option<String>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Parses logs in Rust rather than passing `adb logcat`'s output through.
+    /// Anything other than [`Format::Raw`] reads `-v threadtime` output, so
+    /// `args` shouldn't set a format of their own.
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Filters parsed logs; ignored for [`Format::Raw`].
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Also writes logs to a file, without colors. This is meant to be used
+    /// with a parsed format, since [`Format::Raw`] output is colored by adb.
+    pub fn with_output(mut self, output: Option<OutputFile>) -> Self {
+        self.output = output;
+        self
+    }
+
+    fn record(&self, line: &str) {
+        let mut log_file = self.log_file.lock().unwrap();
+        if let Some(file) = log_file.as_mut() {
+            if let Err(err) = file.write_line(line) {
+                log::error!(
+                    "failed to write to log file {:?}; no longer recording logs: {}",
+                    file.options.path,
+                    err
+                );
+                *log_file = None;
+            }
+        }
+    }
+
+    fn status(&self, msg: impl std::fmt::Display) {
+        let msg = format!("-- {} --", msg);
+        if self.format != Format::Json {
+            self.record(&msg);
+        }
+        let msg = msg.bold();
+        let msg = match &self.prefix {
+            Some(prefix) => format!("{} {}", prefix, msg),
+            None => msg.to_string(),
+        };
+        // Keep stdout valid NDJSON
+        if self.format == Format::Json {
+            eprintln!("{}", msg);
+        } else {
+            println!("{}", msg);
+        }
+    }
+
+    fn print_line(&self, line: &str) {
+        match &self.prefix {
+            Some(prefix) => println!("{} {}", prefix, line),
+            None => println!("{}", line),
+        }
+    }
+
+    fn print_entry(&self, entry: &Entry) {
+        if !self.filter.matches(entry) {
+            return;
+        }
+        match self.format {
+            Format::Raw | Format::Text => {
+                let line = entry.to_string();
+                self.record(&line);
+                self.print_line(&match entry {
+                    Entry::Record(record) => match record.level {
+                        Level::Verbose => line.dimmed(),
+                        Level::Debug => line.blue(),
+                        Level::Info => line.green(),
+                        Level::Warn => line.yellow(),
+                        Level::Error | Level::Fatal => line.red(),
+                        Level::Silent => line.normal(),
+                    }
+                    .to_string(),
+                    Entry::Unparsed { .. } => line,
+                })
+            }
+            Format::Json => match serde_json::to_string(&JsonEntry {
+                serial: &self.serial_no,
+                entry,
+            }) {
+                Ok(json) => {
+                    self.record(&json);
+                    println!("{}", json);
+                }
+                Err(err) => log::error!("failed to serialize log entry: {}", err),
+            },
+        }
+    }
+
+    /// Prints `adb logcat`'s output line by line, parsing it unless the format
+    /// is [`Format::Raw`].
+    fn print_all(&self, reader: &duct::ReaderHandle) {
+        let mut reader = BufReader::new(reader);
+        let mut parser = Parser::new();
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+            let line = String::from_utf8_lossy(&buf);
+            if self.format == Format::Raw {
+                let line = line.trim_end_matches(['\r', '\n']);
+                self.record(line);
+                self.print_line(line);
+                continue;
+            }
+            if let Some(entry) = parser.push(&line) {
+                self.print_entry(&entry);
+            }
+            // A multi-line message arrives in one go, so once we've caught up
+            // the pending record is complete.
+            if reader.buffer().is_empty() {
+                if let Some(entry) = parser.flush() {
+                    self.print_entry(&entry);
+                }
+            }
+        }
+        if let Some(entry) = parser.flush() {
+            self.print_entry(&entry);
+        }
+    }
+
+    fn wait_for_pid(&self, env: &Env, stop: &AtomicBool) -> Result<Option<u32>, Error> {
+        let start = Instant::now();
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            if let Some(pid) = pidof(env, &self.serial_no, &self.package)? {
+                return Ok(Some(pid));
+            }
+            if start.elapsed() >= self.startup_timeout {
+                return Err(Error::StartTimedOut {
+                    package: self.package.clone(),
+                    timeout: self.startup_timeout,
+                });
+            }
+            sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Streams logs for `pid` until the process goes away (or `stop` is set),
+    /// returning the pid of the process that replaced it, if any. Logs of
+    /// other processes are only streamed if the filter selects pids itself.
+    fn follow_pid(&self, env: &Env, pid: u32, stop: &AtomicBool) -> Result<Option<u32>, Error> {
+        let args = self.args.clone();
+        let parsed = self.format != Format::Raw;
+        let only_pid = !(parsed && self.filter.selects_pids());
+        let logcat = adb(env, &self.serial_no)
+            .before_spawn(move |cmd| {
+                cmd.arg("logcat");
+                if parsed {
+                    cmd.args(["-v", "threadtime"]);
+                }
+                cmd.args(&args);
+                if only_pid {
+                    cmd.args(["--pid", &pid.to_string()]);
+                }
+                Ok(())
+            })
+            .unchecked();
+        let (handle, printer) = if self.prefix.is_some() || self.output.is_some() || parsed {
+            let reader = Arc::new(
+                logcat
+                    .stderr_to_stdout()
+                    .reader()
+                    .map_err(Error::LogcatFailed)?,
+            );
+            let printer = {
+                let reader = Arc::clone(&reader);
+                let this = self.clone();
+                thread::spawn(move || this.print_all(&reader))
+            };
+            (LogcatChild::Reader(reader), Some(printer))
+        } else {
+            (
+                LogcatChild::Handle(Box::new(
+                    logcat.dup_stdio().start().map_err(Error::LogcatFailed)?,
+                )),
+                None,
+            )
+        };
+
+        let next = loop {
+            sleep(POLL_INTERVAL);
+            if stop.load(Ordering::Relaxed) || handle.exited() {
+                break Ok(None);
+            }
+            match pidof(env, &self.serial_no, &self.package) {
+                Ok(Some(current)) if current == pid => continue,
+                Ok(next) => break Ok(Some(next)),
+                Err(err) => break Err(err),
+            }
+        };
+        handle.kill();
+        if let Some(printer) = printer {
+            let _ = printer.join();
+        }
+        Ok(match next? {
+            Some(next) => {
+                if crashed(env, &self.serial_no, pid) {
+                    self.status(format!("app crashed (pid {})", pid));
+                } else {
+                    self.status(format!("app exited (pid {})", pid));
+                }
+                next
+            }
+            None => None,
+        })
+    }
+
+    /// Follows the app's logs until it's gone for longer than the startup
+    /// timeout.
+    pub fn follow(&self, env: &Env) -> Result<(), Error> {
+        self.follow_until(env, &AtomicBool::new(false))
+    }
+
+    /// Like [`Self::follow`], but also returns once `stop` is set.
+    pub fn follow_until(&self, env: &Env, stop: &AtomicBool) -> Result<(), Error> {
+        if let Some(output) = &self.output {
+            let log_file = LogFile::open(output).map_err(|source| Error::OutputFailed {
+                path: output.path.clone(),
+                source,
+            })?;
+            *self.log_file.lock().unwrap() = Some(log_file);
+        }
+        self.status(format!("waiting for {} to start", self.package));
+        let mut pid = self.wait_for_pid(env, stop)?;
+        while let Some(current) = pid {
+            self.status(format!("following {} (pid {})", self.package, current));
+            pid = match self.follow_pid(env, current, stop)? {
+                Some(next) => Some(next),
+                None if stop.load(Ordering::Relaxed) => None,
+                None => match self.wait_for_pid(env, stop) {
+                    Ok(next) => next,
+                    Err(Error::StartTimedOut { .. }) => {
+                        self.status(format!(
+                            "{} didn't restart within {}s; no longer following logs",
+                            self.package,
+                            self.startup_timeout.as_secs()
+                        ));
+                        None
+                    }
+                    Err(err) => return Err(err),
+                },
+            };
+        }
+        Ok(())
+    }
+
+    /// Follows the app's logs on a background thread.
+    pub fn start(self, env: &Env) -> LogcatHandle {
+        let env = env.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || self.follow_until(&env, &stop))
+        };
+        LogcatHandle { thread, stop }
+    }
+}
+
+enum LogcatChild {
+    Handle(Box<duct::Handle>),
+    Reader(Arc<duct::ReaderHandle>),
+}
+
+impl LogcatChild {
+    fn exited(&self) -> bool {
+        match self {
+            Self::Handle(handle) => !matches!(handle.try_wait(), Ok(None)),
+            Self::Reader(reader) => !matches!(reader.try_wait(), Ok(None)),
+        }
+    }
+
+    fn kill(&self) {
+        let _ = match self {
+            Self::Handle(handle) => handle.kill(),
+            Self::Reader(reader) => reader.kill(),
+        };
+    }
+}
+
+#[derive(Debug)]
+pub struct LogcatHandle {
+    thread: JoinHandle<Result<(), Error>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl LogcatHandle {
+    /// Blocks until log streaming ends.
+    pub fn wait(self) -> Result<(), Error> {
+        self.thread.join().map_err(|_| Error::ThreadPanicked)?
+    }
+
+    /// Stops streaming and waits for the streaming thread to finish, for
+    /// callers of [`crate::android::device::Device::run`] that are done with
+    /// the app before it exits.
+    pub fn stop(self) -> Result<(), Error> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(max_files, expected,
+        case(2, vec![
+            ("app.log", "6666\n"),
+            ("app.log.1", "4444\n5555\n"),
+            ("app.log.2", "2222\n3333\n"),
+        ]),
+        case(0, vec![("app.log", "6666\n")]),
+    )]
+    fn test_rotate(max_files: usize, expected: Vec<(&str, &str)>) {
+        let dir = std::env::temp_dir().join(format!(
+            "cargo-mobile2-logcat-{}-{}",
+            std::process::id(),
+            max_files
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let mut file = LogFile::open(&OutputFile {
+            path: dir.join("app.log"),
+            // Two lines per file
+            max_size: 10,
+            max_files,
+        })
+        .unwrap();
+        for line in ["0000", "1111", "2222", "3333", "4444", "5555", "6666"] {
+            file.write_line(line).unwrap();
+        }
+        let mut actual = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                (
+                    path.file_name().unwrap().to_string_lossy().into_owned(),
+                    fs::read_to_string(&path).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        actual.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            actual,
+            expected
+                .into_iter()
+                .map(|(name, contents)| (name.to_owned(), contents.to_owned()))
+                .collect::<Vec<_>>()
+        );
+    }
+}
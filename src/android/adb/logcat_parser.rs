@@ -0,0 +1,446 @@
+use crate::opts::FilterLevel;
+use once_cell_regex::{exports::regex::Regex, regex};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Verbose,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+    Silent,
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+impl From<FilterLevel> for Level {
+    fn from(level: FilterLevel) -> Self {
+        match level {
+            FilterLevel::Error => Self::Error,
+            FilterLevel::Warn => Self::Warn,
+            FilterLevel::Info => Self::Info,
+            FilterLevel::Debug => Self::Debug,
+            FilterLevel::Verbose => Self::Verbose,
+        }
+    }
+}
+
+impl Level {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'V' => Some(Self::Verbose),
+            'D' => Some(Self::Debug),
+            'I' => Some(Self::Info),
+            'W' => Some(Self::Warn),
+            'E' => Some(Self::Error),
+            'F' | 'A' => Some(Self::Fatal),
+            'S' => Some(Self::Silent),
+            _ => None,
+        }
+    }
+
+    pub fn as_char(&self) -> char {
+        match self {
+            Self::Verbose => 'V',
+            Self::Debug => 'D',
+            Self::Info => 'I',
+            Self::Warn => 'W',
+            Self::Error => 'E',
+            Self::Fatal => 'F',
+            Self::Silent => 'S',
+        }
+    }
+}
+
+/// A single log message, as printed by `adb logcat -v threadtime`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Record {
+    pub timestamp: String,
+    pub pid: u32,
+    pub tid: u32,
+    pub level: Level,
+    pub tag: String,
+    pub message: String,
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:>5} {:>5} {} {}: {}",
+            self.timestamp, self.pid, self.tid, self.level, self.tag, self.message
+        )
+    }
+}
+
+impl Record {
+    fn from_line(line: &str) -> Option<Self> {
+        let caps = regex!(
+            r"^(?P<timestamp>(?:\d{4}-)?\d{2}-\d{2}\s+\d{2}:\d{2}:\d{2}\.\d{3,9})\s+(?:\S+\s+)?(?P<pid>\d+)\s+(?P<tid>\d+)\s+(?P<level>[VDIWEFAS])\s+(?P<tag>.*?)\s*:(?: (?P<message>.*))?$"
+        )
+        .captures(line)?;
+        Some(Self {
+            timestamp: caps["timestamp"].to_owned(),
+            pid: caps["pid"].parse().ok()?,
+            tid: caps["tid"].parse().ok()?,
+            level: Level::from_char(caps["level"].chars().next()?)?,
+            tag: caps["tag"].to_owned(),
+            message: caps
+                .name("message")
+                .map(|message| message.as_str().to_owned())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// logcat prints each line of a multi-line message with the same header,
+    /// which is all that tells them apart from separate messages logged in the
+    /// same millisecond. So only lines that can't stand on their own, like
+    /// indented stack frames, count as continuations.
+    fn continued_by(&self, other: &Self) -> bool {
+        (other.message.starts_with(char::is_whitespace) || other.message.starts_with("Caused by: "))
+            && self.timestamp == other.timestamp
+            && self.pid == other.pid
+            && self.tid == other.tid
+            && self.level == other.level
+            && self.tag == other.tag
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Entry {
+    Record(Record),
+    /// A line that couldn't be attributed to any record.
+    Unparsed {
+        unparsed: String,
+    },
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Record(record) => write!(f, "{}", record),
+            Self::Unparsed { unparsed } => write!(f, "{}", unparsed),
+        }
+    }
+}
+
+/// Turns logcat output into [`Entry`]s, one line at a time.
+///
+/// Continuation lines of a multi-line message (i.e. stack frames) are merged
+/// into the record they continue, as are lines without a header (which happen
+/// when a message contains raw newlines or the output got garbled). Since the parser can't know whether
+/// a record continues until it sees the next line, the last record is held
+/// back until [`Parser::flush`] is called.
+#[derive(Debug, Default)]
+pub struct Parser {
+    pending: Option<Record>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, line: &str) -> Option<Entry> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.starts_with("--------- ") {
+            // Buffer markers, i.e. "--------- beginning of main"
+            return self.flush();
+        }
+        match Record::from_line(line) {
+            Some(record) => match &mut self.pending {
+                Some(pending) if pending.continued_by(&record) => {
+                    pending.message.push('\n');
+                    pending.message.push_str(&record.message);
+                    None
+                }
+                _ => self.pending.replace(record).map(Entry::Record),
+            },
+            None => match &mut self.pending {
+                Some(pending) => {
+                    pending.message.push('\n');
+                    pending.message.push_str(line);
+                    None
+                }
+                None if line.trim().is_empty() => None,
+                None => Some(Entry::Unparsed {
+                    unparsed: line.to_owned(),
+                }),
+            },
+        }
+    }
+
+    pub fn flush(&mut self) -> Option<Entry> {
+        self.pending.take().map(Entry::Record)
+    }
+}
+
+pub fn parse(text: &str) -> Vec<Entry> {
+    let mut parser = Parser::new();
+    let mut entries = text
+        .lines()
+        .filter_map(|line| parser.push(line))
+        .collect::<Vec<_>>();
+    entries.extend(parser.flush());
+    entries
+}
+
+/// Selects which entries to show. An empty filter lets everything through.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    tags: Vec<String>,
+    min_level: Option<Level>,
+    pattern: Option<Regex>,
+    pids: Vec<u32>,
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tags(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.tags.extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_min_level(mut self, min_level: Option<Level>) -> Self {
+        self.min_level = min_level;
+        self
+    }
+
+    pub fn with_pattern(mut self, pattern: Option<Regex>) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    pub fn with_pids(mut self, pids: impl IntoIterator<Item = u32>) -> Self {
+        self.pids.extend(pids);
+        self
+    }
+
+    /// Whether the filter picks processes itself, rather than relying on
+    /// logcat to only show the app's process.
+    pub fn selects_pids(&self) -> bool {
+        !self.pids.is_empty()
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        match entry {
+            Entry::Record(record) => {
+                (self.tags.is_empty() || self.tags.contains(&record.tag))
+                    && self.min_level.is_none_or(|level| record.level >= level)
+                    && (self.pids.is_empty() || self.pids.contains(&record.pid))
+                    && self.pattern.as_ref().is_none_or(|pattern| {
+                        pattern.is_match(&record.tag) || pattern.is_match(&record.message)
+                    })
+            }
+            Entry::Unparsed { unparsed } => {
+                self.tags.is_empty()
+                    && self.min_level.is_none()
+                    && self.pids.is_empty()
+                    && self
+                        .pattern
+                        .as_ref()
+                        .is_none_or(|pattern| pattern.is_match(unparsed))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn record(
+        timestamp: &str,
+        (pid, tid): (u32, u32),
+        level: Level,
+        tag: &str,
+        message: &str,
+    ) -> Entry {
+        Entry::Record(Record {
+            timestamp: timestamp.to_owned(),
+            pid,
+            tid,
+            level,
+            tag: tag.to_owned(),
+            message: message.to_owned(),
+        })
+    }
+
+    #[test]
+    fn test_parse_threadtime() {
+        let entries = parse(include_str!("fixtures/logcat/threadtime.txt"));
+        assert_eq!(
+            entries,
+            vec![
+                record(
+                    "05-14 10:21:03.112",
+                    (1823, 1823),
+                    Level::Info,
+                    "ActivityManager",
+                    "Start proc 4242:com.example.app/u0a189 for activity {com.example.app/android.app.NativeActivity}",
+                ),
+                record(
+                    "05-14 10:21:03.480",
+                    (4242, 4242),
+                    Level::Debug,
+                    "example",
+                    "initializing renderer",
+                ),
+                record(
+                    "05-14 10:21:03.481",
+                    (4242, 4260),
+                    Level::Warn,
+                    "RustStdoutStderr",
+                    "",
+                ),
+                record(
+                    "05-14 10:21:03.502",
+                    (4242, 4260),
+                    Level::Error,
+                    "example",
+                    "failed to open asset: NotFound",
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_multi_line() {
+        let entries = parse(include_str!("fixtures/logcat/multi_line.txt"));
+        assert_eq!(
+            entries,
+            vec![
+                record(
+                    "05-14 10:22:41.007",
+                    (4242, 4242),
+                    Level::Error,
+                    "AndroidRuntime",
+                    "FATAL EXCEPTION: main",
+                ),
+                record(
+                    "05-14 10:22:41.007",
+                    (4242, 4242),
+                    Level::Error,
+                    "AndroidRuntime",
+                    "Process: com.example.app, PID: 4242",
+                ),
+                record(
+                    "05-14 10:22:41.007",
+                    (4242, 4242),
+                    Level::Error,
+                    "AndroidRuntime",
+                    "java.lang.UnsatisfiedLinkError: dlopen failed: library \"libexample.so\" not found\n\tat java.lang.Runtime.loadLibrary0(Runtime.java:1082)",
+                ),
+                record(
+                    "05-14 10:22:41.010",
+                    (4242, 4242),
+                    Level::Info,
+                    "Process",
+                    "Sending signal. PID: 4242 SIG: 9",
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_same_millisecond() {
+        let entries = parse(include_str!("fixtures/logcat/same_millisecond.txt"));
+        assert_eq!(
+            entries,
+            vec![
+                record(
+                    "05-14 10:25:00.016",
+                    (4242, 4260),
+                    Level::Debug,
+                    "example",
+                    "frame 1 rendered",
+                ),
+                record(
+                    "05-14 10:25:00.016",
+                    (4242, 4260),
+                    Level::Debug,
+                    "example",
+                    "frame 2 rendered",
+                ),
+                record(
+                    "05-14 10:25:00.016",
+                    (4242, 4260),
+                    Level::Error,
+                    "AndroidRuntime",
+                    "java.lang.RuntimeException: render failed\n\tat com.example.app.Renderer.draw(Renderer.java:42)\nCaused by: java.io.IOException: surface lost\n\t... 3 more",
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_garbled() {
+        let entries = parse(include_str!("fixtures/logcat/garbled.txt"));
+        assert_eq!(
+            entries,
+            vec![
+                Entry::Unparsed {
+                    unparsed: "adb: device 'emulator-5554' not found".to_owned(),
+                },
+                record(
+                    "05-14 10:23:12.331",
+                    (4242, 4251),
+                    Level::Info,
+                    "example",
+                    "line with a raw\nnewline in it\n05-14 10:23:1",
+                ),
+                record(
+                    "2024-05-14 10:23:12.400",
+                    (4242, 4251),
+                    Level::Verbose,
+                    "example",
+                    "scope: message: with colons",
+                ),
+            ]
+        );
+    }
+
+    #[rstest(filter, expected,
+        case(Filter::new(), 4),
+        case(Filter::new().with_tags(["example"]), 2),
+        case(Filter::new().with_min_level(Some(Level::Warn)), 2),
+        case(Filter::new().with_pids([1823]), 1),
+        case(Filter::new().with_pattern(Some(Regex::new("(?i)asset|renderer").unwrap())), 2),
+        case(Filter::new().with_tags(["example"]).with_min_level(Some(Level::Error)), 1),
+    )]
+    fn test_filter(filter: Filter, expected: usize) {
+        let entries = parse(include_str!("fixtures/logcat/threadtime.txt"));
+        assert_eq!(
+            entries.iter().filter(|entry| filter.matches(entry)).count(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let entry = record(
+            "05-14 10:21:03.480",
+            (4242, 4242),
+            Level::Debug,
+            "example",
+            "hi",
+        );
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"timestamp":"05-14 10:21:03.480","pid":4242,"tid":4242,"level":"debug","tag":"example","message":"hi"}"#
+        );
+    }
+}
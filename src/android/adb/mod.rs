@@ -1,8 +1,16 @@
+pub mod boot;
 pub mod device_list;
 pub mod device_name;
 pub mod get_prop;
+pub mod logcat;
+pub mod logcat_parser;
+pub mod tombstones;
 
-pub use self::{device_list::device_list, device_name::device_name, get_prop::get_prop};
+pub use self::{
+    device_list::{device_list, serial_list, serial_list_any_state},
+    device_name::device_name,
+    get_prop::get_prop,
+};
 
 use super::env::Env;
 use crate::{env::ExplicitEnv as _, util::cli::Report, DuctExpressionExt};
@@ -0,0 +1,221 @@
+use super::adb;
+use crate::{
+    android::env::Env,
+    util::cli::{Report, Reportable},
+};
+use std::process::Output;
+use thiserror::Error;
+
+static TOMBSTONE_DIR: &str = "/data/tombstones";
+static DROPBOX_SEPARATOR: &str = "========================================";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to list tombstones: {0}")]
+    ListFailed(std::io::Error),
+    #[error("Failed to read tombstone {name}: {source}")]
+    ReadFailed {
+        name: String,
+        source: std::io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ListFailed(err) => Report::error("Failed to list tombstones", err),
+            Self::ReadFailed { name, source } => {
+                Report::error(format!("Failed to read tombstone {}", name), source)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Location {
+    File { path: String, su: bool },
+    Dropbox { contents: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct Tombstone {
+    name: String,
+    location: Location,
+}
+
+impl Tombstone {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn read(&self, env: &Env, serial_no: &str) -> Result<String, Error> {
+        match &self.location {
+            Location::File { path, su } => shell(env, serial_no, *su, &["cat", path])
+                .and_then(|output| {
+                    if output.status.success() {
+                        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+                    } else {
+                        Err(std::io::Error::other(
+                            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+                        ))
+                    }
+                })
+                .map_err(|source| Error::ReadFailed {
+                    name: self.name.clone(),
+                    source,
+                }),
+            Location::Dropbox { contents } => Ok(contents.clone()),
+        }
+    }
+}
+
+fn shell(env: &Env, serial_no: &str, su: bool, args: &[&str]) -> std::io::Result<Output> {
+    let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    adb(env, serial_no)
+        .before_spawn(move |cmd| {
+            cmd.arg("shell");
+            if su {
+                cmd.args(["su", "0"]);
+            }
+            cmd.args(&args);
+            Ok(())
+        })
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+}
+
+/// Lists `/data/tombstones`, which needs root on most devices.
+fn list_dir(env: &Env, serial_no: &str, su: bool) -> Result<Option<Vec<Tombstone>>, Error> {
+    let output =
+        shell(env, serial_no, su, &["ls", "-1t", TOMBSTONE_DIR]).map_err(Error::ListFailed)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() || stdout.contains("Permission denied") {
+        return Ok(None);
+    }
+    Ok(Some(
+        stdout
+            .lines()
+            .map(str::trim)
+            // Android 12+ also writes a protobuf copy of each tombstone
+            .filter(|name| name.starts_with("tombstone_") && !name.ends_with(".pb"))
+            .map(|name| Tombstone {
+                name: name.to_owned(),
+                location: Location::File {
+                    path: format!("{}/{}", TOMBSTONE_DIR, name),
+                    su,
+                },
+            })
+            .collect(),
+    ))
+}
+
+/// Tombstones are also uploaded to the dropbox, which is readable without root.
+fn list_dropbox(env: &Env, serial_no: &str) -> Result<Vec<Tombstone>, Error> {
+    let output = shell(
+        env,
+        serial_no,
+        false,
+        &["dumpsys", "dropbox", "--print", "SYSTEM_TOMBSTONE"],
+    )
+    .map_err(Error::ListFailed)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut tombstones = parse_dropbox(&stdout);
+    // The dropbox lists the oldest entry first
+    tombstones.reverse();
+    Ok(tombstones)
+}
+
+fn parse_dropbox(output: &str) -> Vec<Tombstone> {
+    output
+        .split(DROPBOX_SEPARATOR)
+        .skip(1)
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            let entry = entry.trim_start_matches(['\r', '\n']);
+            let (header, contents) = entry.split_once('\n')?;
+            // i.e. "2024-05-14 10:24:07 SYSTEM_TOMBSTONE (compressed text, 12345 bytes)"
+            let timestamp = header.split(" SYSTEM_TOMBSTONE").next()?.trim();
+            Some(Tombstone {
+                // Timestamps only go down to the second, so the index keeps
+                // crashes from the same second apart
+                name: format!(
+                    "dropbox_{}_{:02}",
+                    timestamp.replace(|c: char| !c.is_ascii_alphanumeric(), "-"),
+                    index
+                ),
+                location: Location::Dropbox {
+                    contents: contents.to_owned(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Lists the tombstones on a device, newest first.
+pub fn list(env: &Env, serial_no: &str) -> Result<Vec<Tombstone>, Error> {
+    for su in [false, true] {
+        if let Some(tombstones) = list_dir(env, serial_no, su)? {
+            return Ok(tombstones);
+        }
+    }
+    log::info!(
+        "couldn't read {}; falling back to `dumpsys dropbox`",
+        TOMBSTONE_DIR
+    );
+    list_dropbox(env, serial_no)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_dropbox() {
+        let tombstones = parse_dropbox(include_str!("fixtures/dropbox/tombstones.txt"));
+        assert_eq!(
+            tombstones.iter().map(Tombstone::name).collect::<Vec<_>>(),
+            [
+                "dropbox_2024-05-14-10-24-07_00",
+                "dropbox_2024-05-14-10-31-52_01"
+            ]
+        );
+        let contents = tombstones
+            .iter()
+            .map(|tombstone| match &tombstone.location {
+                Location::Dropbox { contents } => contents.as_str(),
+                Location::File { .. } => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert!(contents[0].starts_with("*** *** ***"));
+        assert!(contents[0].contains("Abort message: 'attempt to divide by zero'"));
+        assert!(contents[0].trim_end().ends_with("(BuildId: 1f2e3d4c)"));
+        assert!(contents[1].contains("signal 11 (SIGSEGV)"));
+        assert!(!contents[1].contains("Abort message"));
+    }
+
+    #[test]
+    fn test_parse_dropbox_same_second() {
+        let entry = format!(
+            "{}\n2024-05-14 10:24:07 SYSTEM_TOMBSTONE (text, 3 bytes)\nabc\n",
+            DROPBOX_SEPARATOR
+        );
+        let tombstones = parse_dropbox(&entry.repeat(2));
+        assert_eq!(
+            tombstones.iter().map(Tombstone::name).collect::<Vec<_>>(),
+            [
+                "dropbox_2024-05-14-10-24-07_00",
+                "dropbox_2024-05-14-10-24-07_01"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_dropbox_empty() {
+        assert!(parse_dropbox(
+            "Drop box contents: 0 entries\nMax entries: 1000\n\nSearching for: SYSTEM_TOMBSTONE\n\n"
+        )
+        .is_empty());
+    }
+}
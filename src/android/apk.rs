@@ -1,7 +1,8 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use colored::Colorize;
 use heck::ToUpperCamelCase;
+use serde::Deserialize;
 use thiserror::Error;
 
 use super::{config::Config, env::Env, jnilibs, target::Target};
@@ -31,27 +32,47 @@ impl Reportable for ApkError {
     }
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OutputMetadata {
+    elements: Vec<OutputElement>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OutputElement {
+    output_file: String,
+}
+
+/// The APKs gradle reports having built in `output-metadata.json`, so we don't
+/// have to guess whether a release APK was signed.
+fn built_apks(dir: &Path) -> Option<Vec<PathBuf>> {
+    let contents = std::fs::read_to_string(dir.join("output-metadata.json")).ok()?;
+    let metadata = serde_json::from_str::<OutputMetadata>(&contents).ok()?;
+    let paths = metadata
+        .elements
+        .into_iter()
+        .map(|element| dir.join(element.output_file))
+        .collect::<Vec<_>>();
+    (!paths.is_empty()).then_some(paths)
+}
+
 pub fn apks_paths(config: &Config, profile: Profile, flavor: &str) -> Vec<PathBuf> {
-    profile
-        .suffixes()
-        .iter()
-        .map(|suffix| {
-            prefix_path(
-                config.project_dir(),
-                format!(
-                    "app/build/outputs/apk/{}/{}/app-{}-{}.{}",
-                    flavor,
-                    profile.as_str(),
-                    flavor,
-                    suffix,
-                    "apk"
-                ),
-            )
-        })
-        .collect()
+    let dir = prefix_path(
+        config.project_dir(),
+        format!("app/build/outputs/apk/{}/{}", flavor, profile.as_str()),
+    );
+    built_apks(&dir).unwrap_or_else(|| {
+        profile
+            .suffixes()
+            .iter()
+            .map(|suffix| dir.join(format!("app-{}-{}.apk", flavor, suffix)))
+            .collect()
+    })
 }
 
-/// Builds APK(s) and returns the built APK(s) paths
+/// Builds APK(s) and returns the built APK(s) paths. `version_code` overrides
+/// the one the project was generated with.
 pub fn build(
     config: &Config,
     env: &Env,
@@ -59,12 +80,13 @@ pub fn build(
     profile: Profile,
     targets: Vec<&Target>,
     split_per_abi: bool,
+    version_code: Option<u32>,
 ) -> Result<Vec<PathBuf>, ApkError> {
     JniLibs::remove_broken_links(config).map_err(ApkError::LibSymlinkCleaningFailed)?;
 
     let build_ty = profile.as_str().to_upper_camel_case();
 
-    let gradle_args = if split_per_abi {
+    let mut gradle_args = if split_per_abi {
         targets
             .iter()
             .map(|t| format!("assemble{}{}", t.arch_upper_camel_case(), build_ty))
@@ -95,6 +117,9 @@ pub fn build(
 
         args
     };
+    if let Some(version_code) = version_code {
+        gradle_args.push(format!("-PversionCode={}", version_code));
+    }
 
     gradlew(config, env)
         .before_spawn(move |cmd| {
@@ -146,6 +171,7 @@ pub mod cli {
         profile: Profile,
         targets: Vec<&Target>,
         split_per_abi: bool,
+        version_code: Option<u32>,
     ) -> Result<(), ApkError> {
         println!(
             "Building{} APK{} for {} ...\n",
@@ -158,7 +184,15 @@ pub mod cli {
                 .join(", ")
         );
 
-        let outputs = super::build(config, env, noise_level, profile, targets, split_per_abi)?;
+        let outputs = super::build(
+            config,
+            env,
+            noise_level,
+            profile,
+            targets,
+            split_per_abi,
+            version_code,
+        )?;
 
         println!("\nFinished building APK(s):");
         for p in &outputs {
@@ -1,6 +1,7 @@
 #[cfg(not(target_os = "macos"))]
 use crate::util;
 use crate::util::cli::{Report, Reportable};
+use std::path::Path;
 #[cfg(not(target_os = "macos"))]
 use std::path::PathBuf;
 use thiserror::Error;
@@ -37,26 +38,40 @@ impl BundletoolJarInfo {
 
     fn run_command(&self) -> duct::Expression {
         let installation_path = self.installation_path();
-        duct::cmd("java", ["-jar"])
-            .dup_stdio()
-            .before_spawn(move |cmd| {
-                cmd.arg(&installation_path);
-                Ok(())
-            })
+        duct::cmd("java", ["-jar"]).before_spawn(move |cmd| {
+            cmd.arg(&installation_path);
+            Ok(())
+        })
     }
 }
 
-pub fn command() -> duct::Expression {
+fn base_command() -> duct::Expression {
     #[cfg(not(target_os = "macos"))]
     {
         BUNDLE_TOOL_JAR_INFO.run_command()
     }
     #[cfg(target_os = "macos")]
     {
-        duct::cmd!("bundletool").dup_stdio()
+        duct::cmd!("bundletool")
     }
 }
 
+pub fn command() -> duct::Expression {
+    base_command().dup_stdio()
+}
+
+/// Prints the bundle's base module manifest as XML.
+pub fn dump_manifest(aab_path: &Path) -> std::io::Result<String> {
+    let aab_path = aab_path.to_owned();
+    base_command()
+        .before_spawn(move |cmd| {
+            cmd.args(["dump", "manifest", "--bundle"]).arg(&aab_path);
+            Ok(())
+        })
+        .stderr_capture()
+        .read()
+}
+
 #[cfg(target_os = "macos")]
 #[derive(Debug, Error)]
 #[error(transparent)]
@@ -1,10 +1,20 @@
 use crate::{
     android::{
-        aab, adb, apk,
-        config::{Config, Metadata},
-        device::{Device, RunError, StacktraceError},
+        aab,
+        adb::{
+            self,
+            logcat::{LogOptions, OutputFile},
+        },
+        apk,
+        config::{Config, Metadata, TargetNotEnabled},
+        device::{
+            self, AppCommandError, Device, RunAllError, RunError, StacktraceError, TestError,
+            TombstonesError,
+        },
+        device_test, emulator,
         env::{Env, Error as EnvError},
-        target::{BuildError, CompileLibError, Target},
+        inspect, keystore, size,
+        target::{BuildError, CargoMode, CompileLibError, Target},
         DEFAULT_ACTIVITY, NAME,
     },
     config::{
@@ -13,6 +23,7 @@ use crate::{
     },
     define_device_prompt,
     device::PromptError,
+    opts::Profile,
     os,
     target::{call_for_targets_with_fallback, TargetInvalid, TargetTrait as _},
     util::{
@@ -22,7 +33,7 @@ use crate::{
         prompt,
     },
 };
-use std::{ffi::OsString, path::PathBuf};
+use std::{ffi::OsString, path::PathBuf, time::Duration};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -46,6 +57,16 @@ impl Input {
     }
 }
 
+#[derive(Clone, Copy, Debug, StructOpt)]
+pub struct BootTimeout {
+    #[structopt(
+        long = "boot-timeout",
+        help = "Seconds to wait for the device to boot",
+        default_value = "300"
+    )]
+    pub boot_timeout: u64,
+}
+
 #[derive(Clone, Debug, StructOpt)]
 pub enum Command {
     #[structopt(name = "open", about = "Open project in Android Studio")]
@@ -69,16 +90,158 @@ pub enum Command {
         #[structopt(flatten)]
         filter: cli::Filter,
         #[structopt(flatten)]
+        log_filter: cli::LogFilter,
+        #[structopt(flatten)]
         reinstall_deps: cli::ReinstallDeps,
+        #[structopt(flatten)]
+        device: cli::Device,
+        #[structopt(flatten)]
+        all_devices: cli::AllDevices,
         #[structopt(
             short = "a",
             long = "activity",
             help = "Specifies which activtiy to launch"
         )]
         activity: Option<String>,
+        #[structopt(long = "avd", help = "AVD to boot when no device is connected")]
+        avd: Option<String>,
+        #[structopt(
+            long = "startup-timeout",
+            help = "Seconds to wait for the app to start before giving up on its logs",
+            default_value = "60"
+        )]
+        startup_timeout: u64,
+        #[structopt(flatten)]
+        boot_timeout: BootTimeout,
+    },
+    #[structopt(name = "uninstall", about = "Uninstalls the app from a device")]
+    Uninstall {
+        #[structopt(flatten)]
+        device: cli::Device,
+        #[structopt(flatten)]
+        all_devices: cli::AllDevices,
+    },
+    #[structopt(
+        name = "clear-data",
+        about = "Clears the app's data and cache on a device"
+    )]
+    ClearData {
+        #[structopt(flatten)]
+        device: cli::Device,
+        #[structopt(flatten)]
+        all_devices: cli::AllDevices,
+    },
+    #[structopt(name = "stop", about = "Force-stops the app on a device")]
+    Stop {
+        #[structopt(flatten)]
+        device: cli::Device,
+        #[structopt(flatten)]
+        all_devices: cli::AllDevices,
+    },
+    #[structopt(
+        name = "logcat",
+        about = "Follows the logs of the installed app on a device, without rebuilding it"
+    )]
+    Logcat {
+        #[structopt(flatten)]
+        filter: cli::Filter,
+        #[structopt(flatten)]
+        log_filter: cli::LogFilter,
+        #[structopt(flatten)]
+        device: cli::Device,
+        #[structopt(
+            long = "startup-timeout",
+            help = "Seconds to wait for the app to start before giving up on its logs",
+            default_value = "60"
+        )]
+        startup_timeout: u64,
+        #[structopt(
+            long = "output",
+            help = "Also write logs to this file, rotating it as it grows",
+            parse(from_os_str)
+        )]
+        output: Option<PathBuf>,
+        #[structopt(
+            long = "max-file-size",
+            help = "Size in MiB after which the `--output` file is rotated",
+            default_value = "10"
+        )]
+        max_file_size: u64,
+        #[structopt(
+            long = "max-files",
+            help = "Number of rotated `--output` files to keep",
+            default_value = "5"
+        )]
+        max_files: usize,
+    },
+    #[structopt(
+        name = "test",
+        about = "Builds the crate's tests and runs them on a device"
+    )]
+    Test {
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(flatten)]
+        device: cli::Device,
+        #[structopt(flatten)]
+        boot_timeout: BootTimeout,
+        #[structopt(
+            name = "ARGS",
+            last = true,
+            help = "Arguments for the test executables, i.e. a test name filter"
+        )]
+        args: Vec<String>,
+    },
+    #[structopt(
+        name = "bench",
+        about = "Builds the crate's benches and runs them on a device"
+    )]
+    Bench {
+        #[structopt(flatten)]
+        device: cli::Device,
+        #[structopt(flatten)]
+        boot_timeout: BootTimeout,
+        #[structopt(
+            name = "ARGS",
+            last = true,
+            help = "Arguments for the bench executables, i.e. a bench name filter"
+        )]
+        args: Vec<String>,
+    },
+    #[structopt(
+        name = "runner",
+        about = "Runs an executable on a device; used as cargo's runner for Android targets",
+        setting = structopt::clap::AppSettings::Hidden,
+        setting = structopt::clap::AppSettings::TrailingVarArg,
+        setting = structopt::clap::AppSettings::AllowLeadingHyphen
+    )]
+    Runner {
+        #[structopt(name = "EXECUTABLE", parse(from_os_str))]
+        executable: PathBuf,
+        #[structopt(name = "ARGS", allow_hyphen_values = true)]
+        args: Vec<String>,
     },
     #[structopt(name = "st", about = "Displays a detailed stacktrace for a device")]
-    Stacktrace,
+    Stacktrace {
+        #[structopt(flatten)]
+        device: cli::Device,
+    },
+    #[structopt(
+        name = "tombstones",
+        about = "Lists and symbolicates native crash tombstones on a device"
+    )]
+    Tombstones {
+        #[structopt(flatten)]
+        device: cli::Device,
+        #[structopt(
+            long = "pull",
+            help = "Directory to save tombstones to, along with their symbolicated backtraces",
+            parse(from_os_str)
+        )]
+        pull: Option<PathBuf>,
+        #[structopt(long = "latest", help = "Only show the most recent tombstone")]
+        latest: bool,
+    },
     #[structopt(name = "list", about = "Lists connected devices")]
     List,
     #[structopt(name = "apk", about = "Manage and build APKs")]
@@ -91,6 +254,46 @@ pub enum Command {
         #[structopt(subcommand)]
         cmd: AabSubcommand,
     },
+    #[structopt(name = "emulator", about = "Manage Android Virtual Devices")]
+    Emulator {
+        #[structopt(subcommand)]
+        cmd: EmulatorSubcommand,
+    },
+    #[structopt(name = "keystore", about = "Manage signing keystores")]
+    Keystore {
+        #[structopt(subcommand)]
+        cmd: KeystoreSubcommand,
+    },
+    #[structopt(
+        name = "inspect",
+        about = "Shows the native libraries, permissions and versions in an APK or AAB"
+    )]
+    Inspect {
+        #[structopt(name = "PACKAGE", help = "APK or AAB to inspect", parse(from_os_str))]
+        path: PathBuf,
+    },
+    #[structopt(
+        name = "size",
+        about = "Reports the size of the built library per ABI and checks it against the size budget"
+    )]
+    Size {
+        #[structopt(name = "targets", possible_values = &Target::name_list())]
+        /// Which targets to measure (all built ones by default).
+        targets: Vec<String>,
+        #[structopt(flatten)]
+        profile: cli::Profile,
+        #[structopt(
+            long = "top",
+            help = "Number of crates and symbols to list",
+            default_value = "10"
+        )]
+        top: usize,
+        #[structopt(
+            long = "save-baseline",
+            help = "Saves these sizes as the baseline to compare future builds against"
+        )]
+        save_baseline: bool,
+    },
 }
 
 #[derive(StructOpt, Clone, Debug)]
@@ -104,6 +307,11 @@ pub enum ApkSubcommand {
         profile: cli::Profile,
         #[structopt(long = "split-per-abi", help = "Whether to split the APKs per ABIs.")]
         split_per_abi: bool,
+        #[structopt(
+            long = "version-code",
+            help = "Overrides the version code, i.e. with a CI build number"
+        )]
+        version_code: Option<u32>,
     },
 }
 #[derive(StructOpt, Clone, Debug)]
@@ -120,20 +328,182 @@ pub enum AabSubcommand {
     },
 }
 
+#[derive(StructOpt, Clone, Debug)]
+pub enum EmulatorSubcommand {
+    #[structopt(about = "Creates an AVD from a system image, installing the image if needed")]
+    Create {
+        #[structopt(help = "Name of the AVD")]
+        name: String,
+        #[structopt(
+            long = "package",
+            help = "System image to use, i.e. `system-images;android-34;google_apis;x86_64`"
+        )]
+        package: String,
+        #[structopt(
+            long = "device-profile",
+            help = "Hardware profile to use, as listed by `avdmanager list device`"
+        )]
+        device_profile: Option<String>,
+        #[structopt(long = "force", help = "Overwrite an existing AVD with the same name")]
+        force: bool,
+    },
+    #[structopt(about = "Starts an AVD without a window and waits for it to boot")]
+    Start {
+        #[structopt(help = "Name of the AVD")]
+        name: String,
+        #[structopt(long = "window", help = "Show the emulator window")]
+        window: bool,
+        #[structopt(flatten)]
+        boot_timeout: BootTimeout,
+    },
+    #[structopt(about = "Shuts down a running AVD")]
+    Stop {
+        #[structopt(help = "Name of the AVD")]
+        name: String,
+    },
+    #[structopt(about = "Deletes an AVD, stopping it first if it's running")]
+    Delete {
+        #[structopt(help = "Name of the AVD")]
+        name: String,
+    },
+}
+
+#[derive(StructOpt, Clone, Debug)]
+pub struct KeystoreTarget {
+    #[structopt(
+        long = "kind",
+        help = "Whether the keystore signs uploads to Google Play or local debug builds",
+        default_value = "upload",
+        possible_values = keystore::Kind::name_list()
+    )]
+    kind: String,
+    #[structopt(
+        long = "path",
+        help = "Keystore path relative to the app's root directory (defaults to the `signing` metadata, then `upload.jks` or `debug.keystore`)",
+        parse(from_os_str)
+    )]
+    path: Option<PathBuf>,
+    #[structopt(
+        long = "password-env",
+        help = "Environment variable holding the password of an upload keystore (prompted for if unset)"
+    )]
+    password_env: Option<String>,
+}
+
+impl KeystoreTarget {
+    fn kind(&self) -> keystore::Kind {
+        keystore::Kind::from_name(&self.kind).expect("developer error: kind wasn't validated")
+    }
+
+    fn password_env(&self, metadata: &Metadata) -> String {
+        self.password_env
+            .clone()
+            .or_else(|| {
+                metadata
+                    .signing()
+                    .map(|signing| signing.store_password_env().to_owned())
+            })
+            .unwrap_or_else(|| keystore::DEFAULT_PASSWORD_ENV.to_owned())
+    }
+
+    fn keystore(&self, config: &Config, metadata: &Metadata) -> keystore::Keystore {
+        let kind = self.kind();
+        let path = match (&self.path, metadata.signing()) {
+            (Some(path), _) => config.app().prefix_path(path),
+            (None, Some(signing)) if kind == keystore::Kind::Upload => {
+                signing.keystore_path(config.app())
+            }
+            (None, _) => config.app().prefix_path(kind.default_path()),
+        };
+        let password = if kind == keystore::Kind::Debug {
+            keystore::Password::Debug
+        } else {
+            let var = self.password_env(metadata);
+            if std::env::var_os(&var).is_some() {
+                keystore::Password::Env(var)
+            } else {
+                keystore::Password::Prompt
+            }
+        };
+        keystore::Keystore::new(path, password)
+    }
+}
+
+#[derive(StructOpt, Clone, Debug)]
+pub enum KeystoreSubcommand {
+    #[structopt(about = "Generates a keystore and prints its fingerprints")]
+    Create {
+        #[structopt(flatten)]
+        target: KeystoreTarget,
+        #[structopt(
+            long = "alias",
+            help = "Key alias (`upload` or `androiddebugkey` by default)"
+        )]
+        alias: Option<String>,
+        #[structopt(
+            long = "dname",
+            help = "Distinguished name of the certificate (`CN=<app name>` by default)"
+        )]
+        dname: Option<String>,
+        #[structopt(long = "force", help = "Replace an existing keystore")]
+        force: bool,
+        #[structopt(
+            long = "write-metadata",
+            help = "Point the `signing` metadata in Cargo.toml at the new keystore"
+        )]
+        write_metadata: bool,
+    },
+    #[structopt(about = "Prints the contents of a keystore")]
+    Info {
+        #[structopt(flatten)]
+        target: KeystoreTarget,
+    },
+    #[structopt(about = "Prints the SHA-1 and SHA-256 fingerprints of a keystore's keys")]
+    Fingerprint {
+        #[structopt(flatten)]
+        target: KeystoreTarget,
+    },
+}
+
+fn print_fingerprints(keystore: &keystore::Keystore) -> Result<(), keystore::Error> {
+    for fingerprints in keystore.fingerprints()? {
+        println!("{}", fingerprints);
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum Error {
     EnvInitFailed(EnvError),
     DevicePromptFailed(PromptError<adb::device_list::Error>),
     TargetInvalid(TargetInvalid),
+    TargetNotEnabled(TargetNotEnabled),
     ConfigFailed(LoadOrGenError),
     MetadataFailed(metadata::Error),
     Unsupported,
-    ProjectDirAbsent { project_dir: PathBuf },
+    ProjectDirAbsent {
+        project_dir: PathBuf,
+    },
     OpenFailed(os::OpenFileError),
     CheckFailed(CompileLibError),
     BuildFailed(BuildError),
     RunFailed(RunError),
+    RunAllFailed(RunAllError),
+    TestFailed(TestError),
+    RunnerTargetUnknown(PathBuf),
+    RunnerDeviceAbsent {
+        triple: String,
+        connected: Vec<String>,
+    },
+    RunnerFailed(device_test::Error),
     StacktraceFailed(StacktraceError),
+    LogcatFailed(adb::logcat::Error),
+    TombstonesFailed(TombstonesError),
+    AppCommandFailed(AppCommandError),
+    EmulatorFailed(emulator::Error),
+    KeystoreFailed(keystore::Error),
+    InspectFailed(inspect::Error),
+    SizeFailed(size::Error),
     ListFailed(adb::device_list::Error),
     ApkError(apk::ApkError),
     AabError(aab::AabError),
@@ -145,6 +515,7 @@ impl Reportable for Error {
             Self::EnvInitFailed(err) => err.report(),
             Self::DevicePromptFailed(err) => err.report(),
             Self::TargetInvalid(err) => Report::error("Specified target was invalid", err),
+            Self::TargetNotEnabled(err) => err.report(),
             Self::ConfigFailed(err) => err.report(),
             Self::MetadataFailed(err) => err.report(),
             Self::Unsupported => Report::error("Android is marked as unsupported in your Cargo.toml metadata", "If your project should support Android, modify your Cargo.toml, then run `cargo mobile init` and try again."),
@@ -159,7 +530,32 @@ impl Reportable for Error {
             Self::CheckFailed(err) => err.report(),
             Self::BuildFailed(err) => err.report(),
             Self::RunFailed(err) => err.report(),
+            Self::RunAllFailed(err) => err.report(),
+            Self::TestFailed(err) => err.report(),
+            Self::RunnerTargetUnknown(executable) => Report::error(
+                "Failed to detect the executable's target",
+                format!(
+                    "{:?} isn't in a `target/<triple>` directory, and isn't an ELF file for any Android ABI.",
+                    executable
+                ),
+            ),
+            Self::RunnerDeviceAbsent { triple, connected } => Report::action_request(
+                "No connected device can run the executable",
+                format!(
+                    "The executable was built for `{}`, but the connected devices are {}. Connect a device for that target, or build for one of theirs.",
+                    triple,
+                    connected.join(", ")
+                ),
+            ),
+            Self::RunnerFailed(err) => err.report(),
             Self::StacktraceFailed(err) => err.report(),
+            Self::LogcatFailed(err) => err.report(),
+            Self::TombstonesFailed(err) => err.report(),
+            Self::AppCommandFailed(err) => err.report(),
+            Self::EmulatorFailed(err) => err.report(),
+            Self::KeystoreFailed(err) => err.report(),
+            Self::InspectFailed(err) => err.report(),
+            Self::SizeFailed(err) => err.report(),
             Self::ListFailed(err) => err.report(),
             Self::ApkError(err) => err.report(),
             Self::AabError(err) => err.report(),
@@ -167,6 +563,77 @@ impl Reportable for Error {
     }
 }
 
+/// Every connected device, for commands run with `--all-devices`.
+fn connected_devices(env: &Env) -> Result<Vec<Device<'static>>, Error> {
+    let devices = adb::device_list(env)
+        .map_err(|cause| {
+            Error::DevicePromptFailed(PromptError::detection_failed("Android", cause))
+        })?
+        .into_iter()
+        .collect::<Vec<_>>();
+    if devices.is_empty() {
+        return Err(Error::DevicePromptFailed(PromptError::none_detected(
+            "Android",
+        )));
+    }
+    Ok(devices)
+}
+
+fn list_devices(devices: &[Device<'_>]) -> String {
+    devices
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Picks a device for `runner` without prompting or printing anything, since
+/// its output is the output of whatever it runs. Only devices for `target`
+/// are considered, and `ANDROID_SERIAL` has to be set when there's more than
+/// one.
+fn runner_device(env: &Env, target: &Target<'_>) -> Result<Device<'static>, Error> {
+    let connected = connected_devices(env)?;
+    let connected_list = connected.iter().map(crate::device::describe).collect();
+    let mut devices = connected
+        .into_iter()
+        .filter(|device| device.target().triple == target.triple)
+        .collect::<Vec<_>>();
+    if devices.is_empty() {
+        return Err(Error::RunnerDeviceAbsent {
+            triple: target.triple.to_owned(),
+            connected: connected_list,
+        });
+    }
+    match std::env::var("ANDROID_SERIAL")
+        .ok()
+        .filter(|selector| !selector.is_empty())
+    {
+        Some(selector) => {
+            crate::device::select("Android", devices, &selector).map_err(Error::DevicePromptFailed)
+        }
+        None if devices.len() == 1 => Ok(devices.remove(0)),
+        None => Err(Error::DevicePromptFailed(PromptError::selection_required(
+            "Android",
+            devices.iter().map(crate::device::describe).collect(),
+        ))),
+    }
+}
+
+/// Boots `avd` (with a window) if no device is connected, so `run` has
+/// something to run on.
+fn boot_avd_if_disconnected(env: &Env, avd: &str, boot_timeout: Duration) -> Result<(), Error> {
+    let connected = adb::serial_list(env).map_err(|cause| {
+        Error::DevicePromptFailed(PromptError::detection_failed("Android", cause))
+    })?;
+    if connected.is_empty() {
+        println!("No device connected; booting {}...", avd);
+        emulator::Emulator::find(env, avd)
+            .and_then(|emulator| emulator.boot(env, false, boot_timeout))
+            .map_err(Error::EmulatorFailed)?;
+    }
+    Ok(())
+}
+
 impl Exec for Input {
     type Report = Error;
 
@@ -175,9 +642,16 @@ impl Exec for Input {
     }
 
     fn exec(self, wrapper: &TextWrapper) -> Result<(), Self::Report> {
-        define_device_prompt!(adb::device_list, adb::device_list::Error, Android);
-        fn detect_target_ok<'a>(env: &Env) -> Option<&'a Target<'a>> {
-            device_prompt(env).map(|device| device.target()).ok()
+        define_device_prompt!(
+            adb::device_list,
+            adb::device_list::Error,
+            Android,
+            "ANDROID_SERIAL"
+        );
+        fn detect_target_ok<'a>((env, non_interactive): (&Env, bool)) -> Option<&'a Target<'a>> {
+            device_prompt(env, None, non_interactive)
+                .map(|device| device.target())
+                .ok()
         }
 
         fn with_config(
@@ -189,7 +663,7 @@ impl Exec for Input {
                 .map_err(Error::ConfigFailed)?;
             let metadata =
                 OmniMetadata::load(config.app().root_dir()).map_err(Error::MetadataFailed)?;
-            let mut env = Env::new().map_err(Error::EnvInitFailed)?;
+            let mut env = Env::new(Some(config.android())).map_err(Error::EnvInitFailed)?;
 
             if let Some(vars) = metadata.android().env_vars.as_ref() {
                 env.base = env.base.explicit_env_vars(
@@ -217,6 +691,23 @@ impl Exec for Input {
             }
         }
 
+        /// For commands that don't need the Android SDK or NDK.
+        fn with_metadata(
+            non_interactive: bool,
+            wrapper: &TextWrapper,
+            f: impl FnOnce(&Config, &Metadata) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            let (config, _origin) = OmniConfig::load_or_gen(".", non_interactive, wrapper)
+                .map_err(Error::ConfigFailed)?;
+            let metadata =
+                OmniMetadata::load(config.app().root_dir()).map_err(Error::MetadataFailed)?;
+            if metadata.android().supported() {
+                f(config.android(), metadata.android())
+            } else {
+                Err(Error::Unsupported)
+            }
+        }
+
         fn ensure_init(config: &Config) -> Result<(), Error> {
             if !config.project_dir_exists() {
                 Err(Error::ProjectDirAbsent {
@@ -232,10 +723,46 @@ impl Exec for Input {
                 .map_err(Error::OpenFailed)
         }
 
-        fn get_targets_or_all<'a>(targets: Vec<String>) -> Result<Vec<&'a Target<'a>>, Error> {
+        fn app_command(
+            non_interactive: bool,
+            wrapper: &TextWrapper,
+            device: Option<String>,
+            all_devices: bool,
+            done: &str,
+            command: fn(&Device<'static>, &Config, &Env) -> Result<(), AppCommandError>,
+        ) -> Result<(), Error> {
+            with_config(non_interactive, wrapper, |config, _, env| {
+                if all_devices {
+                    let devices = connected_devices(env)?;
+                    device::app_command_all(&devices, config, env, command)
+                        .map_err(Error::AppCommandFailed)?;
+                    println!("{} {}", done, list_devices(&devices));
+                } else {
+                    let device = device_prompt(env, device.as_deref(), non_interactive)
+                        .map_err(Error::DevicePromptFailed)?;
+                    command(&device, config, env).map_err(Error::AppCommandFailed)?;
+                    println!("{} {}", done, device);
+                }
+                Ok(())
+            })
+        }
+
+        fn ensure_enabled(config: &Config, targets: &[String]) -> Result<(), Error> {
+            targets
+                .iter()
+                .filter(|name| Target::for_name(name).is_some())
+                .try_for_each(|name| config.ensure_target_enabled(name))
+                .map_err(Error::TargetNotEnabled)
+        }
+
+        fn get_targets_or_all(
+            config: &Config,
+            targets: Vec<String>,
+        ) -> Result<Vec<&'static Target<'static>>, Error> {
             if targets.is_empty() {
-                Ok(Target::all().iter().map(|t| t.1).collect())
+                Ok(config.targets())
             } else {
+                ensure_enabled(config, &targets)?;
                 let mut outs = Vec::new();
                 for t in targets {
                     let target = Target::for_name(&t)
@@ -265,11 +792,12 @@ impl Exec for Input {
             }),
             Command::Check { targets } => {
                 with_config(non_interactive, wrapper, |config, metadata, env| {
+                    ensure_enabled(config, &targets)?;
                     let force_color = true;
                     call_for_targets_with_fallback(
                         targets.iter(),
                         &detect_target_ok,
-                        env,
+                        (env, non_interactive),
                         |target: &Target| {
                             target
                                 .check(config, metadata, env, noise_level, force_color)
@@ -284,11 +812,12 @@ impl Exec for Input {
                 profile: cli::Profile { profile },
             } => with_config(non_interactive, wrapper, |config, metadata, env| {
                 ensure_init(config)?;
+                ensure_enabled(config, &targets)?;
                 let force_color = true;
                 call_for_targets_with_fallback(
                     targets.iter(),
                     &detect_target_ok,
-                    env,
+                    (env, non_interactive),
                     |target: &Target| {
                         target
                             .build(config, metadata, env, noise_level, force_color, profile)
@@ -300,14 +829,49 @@ impl Exec for Input {
             Command::Run {
                 profile: cli::Profile { profile },
                 filter: cli::Filter { filter },
+                log_filter:
+                    cli::LogFilter {
+                        tags,
+                        grep,
+                        pids,
+                        json,
+                    },
                 reinstall_deps: cli::ReinstallDeps { reinstall_deps },
+                device: cli::Device { device },
+                all_devices: cli::AllDevices { all_devices },
                 activity,
+                avd,
+                startup_timeout,
+                boot_timeout: BootTimeout { boot_timeout },
             } => with_config(non_interactive, wrapper, |config, metadata, env| {
+                let boot_timeout = Duration::from_secs(boot_timeout);
+                if let Some(avd) = avd {
+                    boot_avd_if_disconnected(env, &avd, boot_timeout)?;
+                }
+                let logs = LogOptions {
+                    tags,
+                    pattern: grep,
+                    pids,
+                    json,
+                    output: None,
+                };
                 let build_app_bundle = metadata.asset_packs().is_some();
                 ensure_init(config)?;
-                device_prompt(env)
-                    .map_err(Error::DevicePromptFailed)?
-                    .run(
+                let activity = activity.unwrap_or_else(|| {
+                    metadata
+                        .app_activity_name()
+                        .unwrap_or(DEFAULT_ACTIVITY)
+                        .to_string()
+                });
+                if all_devices {
+                    let devices = connected_devices(env)?;
+                    println!(
+                        "Running on {} connected devices: {}",
+                        devices.len(),
+                        list_devices(&devices)
+                    );
+                    device::run_all(
+                        &devices,
                         config,
                         env,
                         noise_level,
@@ -315,23 +879,255 @@ impl Exec for Input {
                         filter,
                         build_app_bundle,
                         reinstall_deps,
-                        activity.unwrap_or_else(|| {
-                            metadata
-                                .app_activity_name()
-                                .unwrap_or(DEFAULT_ACTIVITY)
-                                .to_string()
-                        }),
+                        activity,
+                        Duration::from_secs(startup_timeout),
+                        boot_timeout,
+                        logs,
+                    )
+                    .map_err(Error::RunAllFailed)
+                } else {
+                    device_prompt(env, device.as_deref(), non_interactive)
+                        .map_err(Error::DevicePromptFailed)?
+                        .run(
+                            config,
+                            env,
+                            noise_level,
+                            profile,
+                            filter,
+                            build_app_bundle,
+                            reinstall_deps,
+                            activity,
+                            Duration::from_secs(startup_timeout),
+                            boot_timeout,
+                            logs,
+                        )
+                        .and_then(|h| h.wait().map_err(RunError::LogcatFailed))
+                        .map_err(Error::RunFailed)
+                }
+            }),
+            Command::Logcat {
+                filter: cli::Filter { filter },
+                log_filter:
+                    cli::LogFilter {
+                        tags,
+                        grep,
+                        pids,
+                        json,
+                    },
+                device: cli::Device { device },
+                startup_timeout,
+                output,
+                max_file_size,
+                max_files,
+            } => with_config(non_interactive, wrapper, |config, _, env| {
+                let logs = LogOptions {
+                    tags,
+                    pattern: grep,
+                    pids,
+                    json,
+                    output: output.map(|path| OutputFile {
+                        path,
+                        max_size: max_file_size.saturating_mul(1024 * 1024),
+                        max_files,
+                    }),
+                };
+                device_prompt(env, device.as_deref(), non_interactive)
+                    .map_err(Error::DevicePromptFailed)?
+                    .logcat(
+                        config,
+                        env,
+                        noise_level,
+                        filter,
+                        Duration::from_secs(startup_timeout),
+                        logs,
+                    )
+                    .map_err(Error::LogcatFailed)
+            }),
+            Command::Uninstall {
+                device: cli::Device { device },
+                all_devices: cli::AllDevices { all_devices },
+            } => app_command(
+                non_interactive,
+                wrapper,
+                device,
+                all_devices,
+                "Uninstalled app from",
+                Device::uninstall,
+            ),
+            Command::ClearData {
+                device: cli::Device { device },
+                all_devices: cli::AllDevices { all_devices },
+            } => app_command(
+                non_interactive,
+                wrapper,
+                device,
+                all_devices,
+                "Cleared app data on",
+                Device::clear_data,
+            ),
+            Command::Stop {
+                device: cli::Device { device },
+                all_devices: cli::AllDevices { all_devices },
+            } => app_command(
+                non_interactive,
+                wrapper,
+                device,
+                all_devices,
+                "Stopped app on",
+                Device::force_stop,
+            ),
+            Command::Test {
+                profile: cli::Profile { profile },
+                device: cli::Device { device },
+                boot_timeout: BootTimeout { boot_timeout },
+                args,
+            } => with_config(non_interactive, wrapper, |config, metadata, env| {
+                device_prompt(env, device.as_deref(), non_interactive)
+                    .map_err(Error::DevicePromptFailed)?
+                    .test(
+                        config,
+                        metadata,
+                        env,
+                        noise_level,
+                        profile,
+                        CargoMode::Test,
+                        Duration::from_secs(boot_timeout),
+                        args,
+                    )
+                    .map_err(Error::TestFailed)
+            }),
+            Command::Bench {
+                device: cli::Device { device },
+                boot_timeout: BootTimeout { boot_timeout },
+                args,
+            } => with_config(non_interactive, wrapper, |config, metadata, env| {
+                device_prompt(env, device.as_deref(), non_interactive)
+                    .map_err(Error::DevicePromptFailed)?
+                    .test(
+                        config,
+                        metadata,
+                        env,
+                        noise_level,
+                        // Passes no `--release`, leaving cargo to use its `bench` profile
+                        Profile::Debug,
+                        CargoMode::Bench,
+                        Duration::from_secs(boot_timeout),
+                        args,
                     )
-                    .and_then(|h| h.wait().map(|_| ()).map_err(Into::into))
-                    .map_err(Error::RunFailed)
+                    .map_err(Error::TestFailed)
             }),
-            Command::Stacktrace => with_config(non_interactive, wrapper, |config, _, env| {
+            Command::Runner { executable, args } => {
+                // cargo runs this from wherever it was invoked, which isn't
+                // necessarily inside a cargo-mobile project
+                let config = OmniConfig::load(".").map_err(Error::ConfigFailed)?;
+                let env = Env::new(config.as_ref().map(OmniConfig::android))
+                    .map_err(Error::EnvInitFailed)?;
+                let target = device_test::executable_target(&executable)
+                    .ok_or_else(|| Error::RunnerTargetUnknown(executable.clone()))?;
+                let device = runner_device(&env, target)?;
+                log::info!(
+                    "running {:?} with args {:?} on {}",
+                    executable,
+                    args,
+                    device
+                );
+                device_test::push(
+                    &env,
+                    device.serial_no(),
+                    target,
+                    std::slice::from_ref(&executable),
+                )
+                .and_then(|()| {
+                    device_test::run_executable(&env, device.serial_no(), &executable, &args)
+                })
+                .map_err(Error::RunnerFailed)
+                .map(|status| {
+                    if !status.success() {
+                        // Relay the exact exit code, i.e. 101 for failed tests
+                        std::process::exit(status.code().unwrap_or(1));
+                    }
+                })
+            }
+            Command::Stacktrace {
+                device: cli::Device { device },
+            } => with_config(non_interactive, wrapper, |config, _, env| {
                 ensure_init(config)?;
-                device_prompt(env)
+                device_prompt(env, device.as_deref(), non_interactive)
                     .map_err(Error::DevicePromptFailed)?
                     .stacktrace(config, env)
                     .map_err(Error::StacktraceFailed)
             }),
+            Command::Tombstones {
+                device: cli::Device { device },
+                pull,
+                latest,
+            } => with_config(non_interactive, wrapper, |config, _, env| {
+                ensure_init(config)?;
+                device_prompt(env, device.as_deref(), non_interactive)
+                    .map_err(Error::DevicePromptFailed)?
+                    .tombstones(config, env, pull.as_deref(), latest)
+                    .map_err(Error::TombstonesFailed)
+            }),
+            Command::Inspect { path } => with_config(non_interactive, wrapper, |config, _, env| {
+                let contents = inspect::Contents::read(env, &path).map_err(Error::InspectFailed)?;
+                println!("{}", contents);
+                let missing = contents.missing_libs(&[&config.so_name()]);
+                if missing.is_empty() {
+                    Ok(())
+                } else {
+                    Err(Error::InspectFailed(inspect::Error::LibsMissing(missing)))
+                }
+            }),
+            Command::Size {
+                targets,
+                profile: cli::Profile { profile },
+                top,
+                save_baseline,
+            } => with_config(non_interactive, wrapper, |config, metadata, env| {
+                let explicit = !targets.is_empty();
+                let mut libs = Vec::new();
+                for target in get_targets_or_all(config, targets)? {
+                    match size::LibSize::measure(config, &env.ndk, target, profile) {
+                        Ok(lib) => libs.push(lib),
+                        Err(size::Error::LibMissing(_)) if !explicit => {}
+                        Err(err) => return Err(Error::SizeFailed(err)),
+                    }
+                }
+                if libs.is_empty() {
+                    return Err(Error::SizeFailed(size::Error::LibMissing(
+                        config
+                            .app()
+                            .target_dir("<target>", profile)
+                            .join(config.so_name()),
+                    )));
+                }
+                let default_budget = Default::default();
+                let budget = metadata.size_budget().unwrap_or(&default_budget);
+                let baseline_path = budget.baseline_path(config.app());
+                let baseline = if save_baseline {
+                    None
+                } else {
+                    size::Baseline::load(&baseline_path, profile).map_err(Error::SizeFailed)?
+                };
+                for lib in &libs {
+                    let old_size = baseline
+                        .as_ref()
+                        .and_then(|baseline| baseline.sizes.get(&lib.abi).copied());
+                    println!("{}\n", lib.display(top, old_size));
+                }
+                if save_baseline {
+                    size::Baseline::new(profile, &libs)
+                        .save(&baseline_path)
+                        .map_err(Error::SizeFailed)?;
+                    println!("Saved baseline to {:?}", baseline_path);
+                }
+                let violations = size::check_budget(budget, &libs, baseline.as_ref());
+                if violations.is_empty() {
+                    Ok(())
+                } else {
+                    Err(Error::SizeFailed(size::Error::OverBudget(violations)))
+                }
+            }),
             Command::List => with_config(non_interactive, wrapper, |_, _, env| {
                 adb::device_list(env)
                     .map_err(Error::ListFailed)
@@ -344,6 +1140,7 @@ impl Exec for Input {
                     targets,
                     profile: cli::Profile { profile },
                     split_per_abi,
+                    version_code,
                 } => with_config(non_interactive, wrapper, |config, _, env| {
                     ensure_init(config)?;
 
@@ -352,8 +1149,9 @@ impl Exec for Input {
                         env,
                         noise_level,
                         profile,
-                        get_targets_or_all(targets)?,
+                        get_targets_or_all(config, targets)?,
                         split_per_abi,
+                        version_code,
                     )
                     .map_err(Error::ApkError)
                 }),
@@ -370,12 +1168,100 @@ impl Exec for Input {
                         env,
                         noise_level,
                         profile,
-                        get_targets_or_all(targets)?,
+                        get_targets_or_all(config, targets)?,
                         split_per_abi,
                     )
                     .map_err(Error::AabError)
                 }),
             },
+            Command::Emulator { cmd } => with_config(non_interactive, wrapper, |_, _, env| {
+                match cmd {
+                    EmulatorSubcommand::Create {
+                        name,
+                        package,
+                        device_profile,
+                        force,
+                    } => emulator::Emulator::create(
+                        env,
+                        &name,
+                        &package,
+                        device_profile.as_deref(),
+                        force,
+                        non_interactive,
+                    )
+                    .map(|_| ()),
+                    EmulatorSubcommand::Start {
+                        name,
+                        window,
+                        boot_timeout: BootTimeout { boot_timeout },
+                    } => emulator::Emulator::find(env, &name)
+                        .and_then(|emulator| {
+                            emulator.boot(env, !window, Duration::from_secs(boot_timeout))
+                        })
+                        .map(|serial_no| println!("{} booted as {}", name, serial_no)),
+                    EmulatorSubcommand::Stop { name } => {
+                        emulator::Emulator::find(env, &name).and_then(|emulator| emulator.stop(env))
+                    }
+                    EmulatorSubcommand::Delete { name } => emulator::Emulator::find(env, &name)
+                        .and_then(|emulator| emulator.delete(env)),
+                }
+                .map_err(Error::EmulatorFailed)
+            }),
+            Command::Keystore { cmd } => {
+                with_metadata(non_interactive, wrapper, |config, metadata| {
+                    match cmd {
+                        KeystoreSubcommand::Create {
+                            target,
+                            alias,
+                            dname,
+                            force,
+                            write_metadata,
+                        } => {
+                            let kind = target.kind();
+                            if write_metadata && kind == keystore::Kind::Debug {
+                                return Err(Error::KeystoreFailed(
+                                    keystore::Error::DebugSigningUnsupported,
+                                ));
+                            }
+                            let keystore = target.keystore(config, metadata);
+                            let alias = alias.as_deref().unwrap_or(kind.default_alias());
+                            let dname = dname.or_else(|| {
+                                (kind == keystore::Kind::Upload)
+                                    .then(|| format!("CN={}", config.app().name()))
+                            });
+                            keystore
+                                .create(alias, dname.as_deref(), kind, force)
+                                .and_then(|()| {
+                                    println!("Created {:?}", keystore.path());
+                                    print_fingerprints(&keystore)
+                                })
+                                .and_then(|()| {
+                                    if !write_metadata {
+                                        return Ok(());
+                                    }
+                                    keystore::write_signing_metadata(
+                                        config.app(),
+                                        keystore.path(),
+                                        alias,
+                                        &target.password_env(metadata),
+                                    )?;
+                                    println!(
+                                        "Updated the signing metadata in {:?}; run `cargo mobile init` to apply it",
+                                        config.app().manifest_path()
+                                    );
+                                    Ok(())
+                                })
+                        }
+                        KeystoreSubcommand::Info { target } => {
+                            target.keystore(config, metadata).print_info()
+                        }
+                        KeystoreSubcommand::Fingerprint { target } => {
+                            print_fingerprints(&target.keystore(config, metadata))
+                        }
+                    }
+                    .map_err(Error::KeystoreFailed)
+                })
+            }
         }
     }
 }
@@ -1,18 +1,23 @@
+use super::{ndk, target::Target};
 use crate::{
     config::app::App,
+    target::{TargetInvalid, TargetTrait as _},
     util::{self, cli::Report},
 };
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     fmt::{self, Display},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use thiserror::Error;
 
 const DEFAULT_MIN_SDK_VERSION: u32 = 24;
+const DEFAULT_TARGET_SDK_VERSION: u32 = 33;
+const DEFAULT_COMPILE_SDK_VERSION: u32 = 33;
 pub const DEFAULT_VULKAN_VALIDATION: bool = true;
 static DEFAULT_PROJECT_DIR: &str = "gen/android";
+static DEFAULT_SIZE_BASELINE_PATH: &str = "android-size-baseline.json";
 
 const fn default_true() -> bool {
     true
@@ -24,6 +29,61 @@ pub struct AssetPackInfo {
     pub delivery_type: String,
 }
 
+/// Release signing config. Passwords are read from environment variables when
+/// gradle runs, so they never end up in `Cargo.toml` or the generated project.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Signing {
+    /// Relative to the app's root directory.
+    pub keystore_path: PathBuf,
+    pub key_alias: String,
+    pub store_password_env: String,
+    /// Defaults to `store-password-env`.
+    pub key_password_env: Option<String>,
+}
+
+impl Signing {
+    pub fn keystore_path(&self, app: &App) -> PathBuf {
+        app.prefix_path(&self.keystore_path)
+    }
+
+    pub fn key_alias(&self) -> &str {
+        &self.key_alias
+    }
+
+    pub fn store_password_env(&self) -> &str {
+        &self.store_password_env
+    }
+
+    pub fn key_password_env(&self) -> &str {
+        self.key_password_env
+            .as_deref()
+            .unwrap_or(&self.store_password_env)
+    }
+}
+
+/// Limits `cargo android size` enforces on each ABI's library.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SizeBudget {
+    /// In bytes, measured after stripping for release builds.
+    pub max_size: Option<u64>,
+    /// Growth allowed relative to the saved baseline.
+    pub max_growth_percent: Option<f64>,
+    /// Relative to the app's root directory.
+    pub baseline_path: Option<PathBuf>,
+}
+
+impl SizeBudget {
+    pub fn baseline_path(&self, app: &App) -> PathBuf {
+        app.prefix_path(
+            self.baseline_path
+                .as_deref()
+                .unwrap_or(Path::new(DEFAULT_SIZE_BASELINE_PATH)),
+        )
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Metadata {
@@ -44,6 +104,11 @@ pub struct Metadata {
     pub app_theme_parent: Option<String>,
     pub env_vars: Option<HashMap<String, String>>,
     pub vulkan_validation: Option<bool>,
+    pub signing: Option<Signing>,
+    pub version_name: Option<String>,
+    pub version_code: Option<u32>,
+    pub version_code_formula: Option<String>,
+    pub size_budget: Option<SizeBudget>,
 }
 
 impl Default for Metadata {
@@ -64,6 +129,11 @@ impl Default for Metadata {
             app_theme_parent: None,
             env_vars: None,
             vulkan_validation: None,
+            signing: None,
+            version_name: None,
+            version_code: None,
+            version_code_formula: None,
+            size_budget: None,
         }
     }
 }
@@ -124,6 +194,28 @@ impl Metadata {
     pub fn vulkan_validation(&self) -> Option<bool> {
         self.vulkan_validation
     }
+
+    pub fn signing(&self) -> Option<&Signing> {
+        self.signing.as_ref()
+    }
+
+    pub fn version_name(&self) -> Option<&str> {
+        self.version_name.as_deref()
+    }
+
+    pub fn version_code(&self) -> Option<u32> {
+        self.version_code
+    }
+
+    pub fn version_code_formula(&self) -> &str {
+        self.version_code_formula
+            .as_deref()
+            .unwrap_or(super::version::DEFAULT_VERSION_CODE_FORMULA)
+    }
+
+    pub fn size_budget(&self) -> Option<&SizeBudget> {
+        self.size_budget.as_ref()
+    }
 }
 
 #[derive(Debug)]
@@ -168,6 +260,14 @@ impl Display for ProjectDirInvalid {
 pub enum Error {
     #[error("android.project-dir invalid: {0}")]
     ProjectDirInvalid(ProjectDirInvalid),
+    #[error("SDK versions must satisfy min-sdk-version <= target-sdk-version <= compile-sdk-version, but they're {min}, {target} and {compile}")]
+    SdkVersionsInvalid { min: u32, target: u32, compile: u32 },
+    #[error("android.targets can't be empty")]
+    TargetsEmpty,
+    #[error("android.targets invalid: {0}")]
+    TargetsInvalid(TargetInvalid),
+    #[error("android.ndk-version invalid: {0}")]
+    NdkVersionInvalid(ndk::VersionReqInvalid),
 }
 
 impl Error {
@@ -176,15 +276,35 @@ impl Error {
     }
 }
 
+#[derive(Debug, Error)]
+#[error("Target {name:?} isn't enabled; the enabled targets are {enabled:?}. Add it to `android.targets` in your config to build it.")]
+pub struct TargetNotEnabled {
+    pub name: String,
+    pub enabled: Vec<String>,
+}
+
+impl TargetNotEnabled {
+    pub fn report(&self) -> Report {
+        Report::action_request("Specified target isn't enabled", self)
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Raw {
     pub min_sdk_version: Option<u32>,
+    pub target_sdk_version: Option<u32>,
+    pub compile_sdk_version: Option<u32>,
     pub project_dir: Option<String>,
     pub no_default_features: Option<bool>,
     pub features: Option<Vec<String>>,
     #[serde(default)]
     pub logcat_filter_specs: Vec<String>,
+    /// Names of the targets to build, i.e. `["aarch64", "x86_64"]`.
+    pub targets: Option<Vec<String>>,
+    /// Which of the NDKs installed in the SDK to use when `NDK_HOME` isn't
+    /// set, i.e. `"26.1.10909125"` or `">=25"`.
+    pub ndk_version: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -193,8 +313,13 @@ pub struct Config {
     #[serde(skip_serializing)]
     app: App,
     min_sdk_version: u32,
+    target_sdk_version: u32,
+    compile_sdk_version: u32,
     project_dir: PathBuf,
     logcat_filter_specs: Vec<String>,
+    targets: Vec<String>,
+    #[serde(skip_serializing)]
+    ndk_version: Option<ndk::VersionReq>,
 }
 
 impl Config {
@@ -202,6 +327,21 @@ impl Config {
         let raw = raw.unwrap_or_default();
 
         let min_sdk_version = raw.min_sdk_version.unwrap_or(DEFAULT_MIN_SDK_VERSION);
+        // Unset versions are raised to fit the ones that are set, so only
+        // explicitly conflicting versions are errors
+        let target_sdk_version = raw
+            .target_sdk_version
+            .unwrap_or_else(|| DEFAULT_TARGET_SDK_VERSION.max(min_sdk_version));
+        let compile_sdk_version = raw
+            .compile_sdk_version
+            .unwrap_or_else(|| DEFAULT_COMPILE_SDK_VERSION.max(target_sdk_version));
+        if !(min_sdk_version <= target_sdk_version && target_sdk_version <= compile_sdk_version) {
+            return Err(Error::SdkVersionsInvalid {
+                min: min_sdk_version,
+                target: target_sdk_version,
+                compile: compile_sdk_version,
+            });
+        }
 
         let project_dir = if let Some(project_dir) = raw.project_dir {
             if project_dir == DEFAULT_PROJECT_DIR {
@@ -235,11 +375,51 @@ impl Config {
             Ok(DEFAULT_PROJECT_DIR.into())
         }?;
 
+        let targets = if let Some(targets) = raw.targets {
+            if targets.is_empty() {
+                return Err(Error::TargetsEmpty);
+            }
+            if let Some(name) = targets.iter().find(|name| Target::for_name(name).is_none()) {
+                return Err(Error::TargetsInvalid(TargetInvalid {
+                    name: name.clone(),
+                    possible: Target::name_list()
+                        .into_iter()
+                        .map(ToOwned::to_owned)
+                        .collect(),
+                }));
+            }
+            // Kept in the same order as `Target::all`, since the template
+            // lists are matched up by index
+            Target::all()
+                .keys()
+                .filter(|name| targets.iter().any(|enabled| enabled == *name))
+                .map(|name| (*name).to_owned())
+                .collect()
+        } else {
+            // Targets rustup doesn't ship a standard library for have to be
+            // opted into
+            Target::all()
+                .iter()
+                .filter(|(_, target)| target.prebuilt_std())
+                .map(|(name, _)| (*name).to_owned())
+                .collect()
+        };
+
+        let ndk_version = raw
+            .ndk_version
+            .map(|version| version.parse())
+            .transpose()
+            .map_err(Error::NdkVersionInvalid)?;
+
         Ok(Self {
             app,
             min_sdk_version,
+            target_sdk_version,
+            compile_sdk_version,
             project_dir,
             logcat_filter_specs: raw.logcat_filter_specs,
+            targets,
+            ndk_version,
         })
     }
 
@@ -251,6 +431,47 @@ impl Config {
         &self.logcat_filter_specs
     }
 
+    /// The enabled targets, in the same order as `Target::all`.
+    pub fn targets(&self) -> Vec<&'static Target<'static>> {
+        self.targets
+            .iter()
+            .filter_map(|name| Target::for_name(name))
+            .collect()
+    }
+
+    pub fn target_enabled(&self, name: &str) -> bool {
+        self.targets.iter().any(|enabled| enabled == name)
+    }
+
+    pub fn ensure_target_enabled(&self, name: &str) -> Result<(), TargetNotEnabled> {
+        if self.target_enabled(name) {
+            Ok(())
+        } else {
+            Err(TargetNotEnabled {
+                name: name.to_owned(),
+                enabled: self.targets.clone(),
+            })
+        }
+    }
+
+    pub fn target_names(&self) -> &[String] {
+        &self.targets
+    }
+
+    pub fn ndk_version(&self) -> Option<&ndk::VersionReq> {
+        self.ndk_version.as_ref()
+    }
+
+    /// The application ID (i.e. `com.example.my_app`), which is also the
+    /// package name on devices.
+    pub fn app_id(&self) -> String {
+        format!(
+            "{}.{}",
+            self.app().reverse_domain(),
+            self.app().name_snake()
+        )
+    }
+
     pub fn so_name(&self) -> String {
         format!("lib{}.so", self.app().lib_name())
     }
@@ -259,6 +480,14 @@ impl Config {
         self.min_sdk_version
     }
 
+    pub fn target_sdk_version(&self) -> u32 {
+        self.target_sdk_version
+    }
+
+    pub fn compile_sdk_version(&self) -> u32 {
+        self.compile_sdk_version
+    }
+
     pub fn project_dir(&self) -> PathBuf {
         self.app.prefix_path(&self.project_dir)
     }
@@ -1,20 +1,31 @@
-use super::{aab, adb, bundletool, config::Config, env::Env, jnilibs, target::Target};
+use super::{
+    aab,
+    adb::{
+        self,
+        logcat::{Format, LogOptions, Logcat, LogcatHandle},
+        logcat_parser::Filter,
+    },
+    bundletool,
+    config::{Config, Metadata, TargetNotEnabled},
+    device_test,
+    env::Env,
+    symbolicator::{self, Symbolicator},
+    target::{CargoMode, CompileLibError, Target},
+};
 use crate::{
     android::apk,
-    env::ExplicitEnv as _,
     opts::{FilterLevel, NoiseLevel, Profile},
-    os::consts,
     util::{
-        self,
         cli::{Report, Reportable},
         last_modified, prefix_path,
     },
     DuctExpressionExt,
 };
+use colored::{Color, Colorize as _};
 use std::{
     fmt::{self, Display},
-    path::PathBuf,
-    thread::sleep,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
 use thiserror::Error;
@@ -84,6 +95,12 @@ pub enum RunError {
     #[error(transparent)]
     ApksFromAabBuildFailed(ApksBuildError),
     #[error(transparent)]
+    LogcatFailed(adb::logcat::Error),
+    #[error(transparent)]
+    BootFailed(adb::boot::Error),
+    #[error(transparent)]
+    TargetNotEnabled(TargetNotEnabled),
+    #[error(transparent)]
     Io(#[from] std::io::Error),
 }
 
@@ -97,25 +114,231 @@ impl Reportable for RunError {
             Self::BundletoolInstallFailed(err) => err.report(),
             Self::AabBuildFailed(err) => err.report(),
             Self::ApksFromAabBuildFailed(err) => err.report(),
+            Self::LogcatFailed(err) => err.report(),
+            Self::BootFailed(err) => err.report(),
+            Self::TargetNotEnabled(err) => err.report(),
             Self::Io(err) => Report::error("IO error", err),
         }
     }
 }
 
+#[derive(Debug, Error)]
+pub enum RunAllError {
+    #[error(transparent)]
+    TargetNotEnabled(TargetNotEnabled),
+    #[error(transparent)]
+    ApkError(apk::ApkError),
+    #[error(transparent)]
+    AabError(aab::AabError),
+    #[error(transparent)]
+    BundletoolInstallFailed(bundletool::InstallError),
+    #[error("Failed to run on {} of {total} devices", failures.len())]
+    DevicesFailed {
+        total: usize,
+        failures: Vec<(String, RunError)>,
+    },
+}
+
+impl Reportable for RunAllError {
+    fn report(&self) -> Report {
+        match self {
+            Self::TargetNotEnabled(err) => err.report(),
+            Self::ApkError(err) => err.report(),
+            Self::AabError(err) => err.report(),
+            Self::BundletoolInstallFailed(err) => err.report(),
+            Self::DevicesFailed { failures, .. } => Report::error(
+                self,
+                failures
+                    .iter()
+                    .map(|(device, err)| format!("{}: {}", device, err))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum StacktraceError {
+    #[error("Failed to read device logs: {0}")]
+    LogcatFailed(std::io::Error),
     #[error(transparent)]
-    Io(#[from] std::io::Error),
+    SymbolicationFailed(#[from] symbolicator::Error),
 }
 
 impl Reportable for StacktraceError {
     fn report(&self) -> Report {
         match self {
-            Self::Io(err) => Report::error("IO error", err),
+            Self::LogcatFailed(err) => Report::error("Failed to read device logs", err),
+            Self::SymbolicationFailed(err) => err.report(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TestError {
+    #[error(transparent)]
+    TargetNotEnabled(TargetNotEnabled),
+    #[error(transparent)]
+    BuildFailed(CompileLibError),
+    #[error("No {0} executables were built")]
+    NoneBuilt(CargoMode),
+    #[error(transparent)]
+    BootFailed(adb::boot::Error),
+    #[error(transparent)]
+    RunFailed(device_test::Error),
+}
+
+impl Reportable for TestError {
+    fn report(&self) -> Report {
+        match self {
+            Self::TargetNotEnabled(err) => err.report(),
+            Self::BuildFailed(err) => err.report(),
+            Self::NoneBuilt(_) => Report::error("Nothing to run", self),
+            Self::BootFailed(err) => err.report(),
+            Self::RunFailed(err) => err.report(),
         }
     }
 }
 
+#[derive(Debug, Error)]
+pub enum AppCommandError {
+    #[error("Failed to run `adb {command}`: {source}")]
+    CommandFailed {
+        command: String,
+        source: std::io::Error,
+    },
+    #[error("`adb {command}` failed: {output}")]
+    Rejected { command: String, output: String },
+    #[error("Failed on {} of {total} devices", failures.len())]
+    DevicesFailed {
+        total: usize,
+        failures: Vec<(String, AppCommandError)>,
+    },
+}
+
+impl Reportable for AppCommandError {
+    fn report(&self) -> Report {
+        match self {
+            Self::CommandFailed { command, source } => {
+                Report::error(format!("Failed to run `adb {}`", command), source)
+            }
+            Self::Rejected { .. } => Report::error("Device rejected the command", self),
+            Self::DevicesFailed { failures, .. } => Report::error(
+                self,
+                failures
+                    .iter()
+                    .map(|(device, err)| format!("{}: {}", device, err))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TombstonesError {
+    #[error(transparent)]
+    ListFailed(#[from] adb::tombstones::Error),
+    #[error(transparent)]
+    SymbolicationFailed(#[from] symbolicator::Error),
+    #[error("Failed to write {path:?}: {source}")]
+    WriteFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+impl Reportable for TombstonesError {
+    fn report(&self) -> Report {
+        match self {
+            Self::ListFailed(err) => err.report(),
+            Self::SymbolicationFailed(err) => err.report(),
+            Self::WriteFailed { path, source } => {
+                Report::error(format!("Failed to write {:?}", path), source)
+            }
+        }
+    }
+}
+
+/// Everything needed to follow the app's logs once it's launched, resolved
+/// up-front so it can be shared between devices.
+#[derive(Debug)]
+struct Launch {
+    package: String,
+    app_name: String,
+    filter_level: FilterLevel,
+    logcat_filter_specs: Vec<String>,
+    startup_timeout: Duration,
+    logs: LogOptions,
+}
+
+impl Launch {
+    fn new(
+        config: &Config,
+        noise_level: NoiseLevel,
+        filter_level: Option<FilterLevel>,
+        startup_timeout: Duration,
+        logs: LogOptions,
+    ) -> Self {
+        Self {
+            package: config.app_id(),
+            app_name: config.app().name().to_owned(),
+            filter_level: filter_level.unwrap_or(match noise_level {
+                NoiseLevel::Polite => FilterLevel::Warn,
+                NoiseLevel::LoudAndProud => FilterLevel::Info,
+                NoiseLevel::FranklyQuitePedantic => FilterLevel::Verbose,
+            }),
+            logcat_filter_specs: config.logcat_filter_specs().to_vec(),
+            startup_timeout,
+            logs,
+        }
+    }
+
+    fn logcat(&self, serial_no: &str) -> Logcat {
+        let format = self.logs.format();
+        let logcat = Logcat::new(serial_no, &self.package)
+            .with_format(format)
+            .with_startup_timeout(self.startup_timeout)
+            .with_output(self.logs.output.clone());
+        // Explicit tags replace the `-s` filter, so the level has to be
+        // checked on our end instead.
+        if self.logs.tags.is_empty() {
+            let filter = format!("{}:{}", self.app_name, self.filter_level.logcat());
+            logcat
+                .with_args(if format == Format::Raw {
+                    vec!["-v", "color", "-s", &filter]
+                } else {
+                    vec!["-s", &filter]
+                })
+                .with_args(&self.logcat_filter_specs)
+                .with_filter(
+                    Filter::new()
+                        .with_pattern(self.logs.pattern.clone())
+                        .with_pids(self.logs.pids.iter().copied()),
+                )
+        } else {
+            logcat.with_filter(
+                Filter::new()
+                    .with_tags(&self.logs.tags)
+                    .with_min_level(Some(self.filter_level.into()))
+                    .with_pattern(self.logs.pattern.clone())
+                    .with_pids(self.logs.pids.iter().copied()),
+            )
+        }
+    }
+}
+
+/// A universal build that can be installed on any connected device.
+#[derive(Debug)]
+enum Artifact {
+    Apk(PathBuf),
+    Aab {
+        aab_path: PathBuf,
+        apks_dir: PathBuf,
+    },
+}
+
 #[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Device<'a> {
     serial_no: String,
@@ -134,6 +357,18 @@ impl<'a> Display for Device<'a> {
     }
 }
 
+impl<'a> crate::device::Selectable for Device<'a> {
+    fn id(&self) -> &str {
+        &self.serial_no
+    }
+
+    fn matches(&self, selector: &str) -> bool {
+        self.serial_no == selector
+            || self.name.eq_ignore_ascii_case(selector)
+            || self.model.eq_ignore_ascii_case(selector)
+    }
+}
+
 impl<'a> Device<'a> {
     pub(super) fn new(
         serial_no: String,
@@ -153,6 +388,10 @@ impl<'a> Device<'a> {
         self.target
     }
 
+    pub fn serial_no(&self) -> &str {
+        &self.serial_no
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -185,31 +424,8 @@ impl<'a> Device<'a> {
             .collect()
     }
 
-    fn wait_device_boot(&self, env: &Env) {
-        loop {
-            let cmd = self
-                .adb(env)
-                .stderr_capture()
-                .stdout_capture()
-                .before_spawn(move |cmd| {
-                    cmd.args(["shell", "getprop", "init.svc.bootanim"]);
-                    Ok(())
-                });
-            let handle = cmd.start();
-            if let Ok(handle) = handle {
-                if let Ok(output) = handle.wait() {
-                    if output.status.success() {
-                        let stdout = String::from_utf8_lossy(&output.stdout);
-                        if stdout.trim() == "stopped" {
-                            break;
-                        }
-                        sleep(Duration::from_secs(2));
-                    }
-                } else {
-                    break;
-                }
-            }
-        }
+    fn wait_for_boot(&self, env: &Env, timeout: Duration) -> Result<(), RunError> {
+        adb::boot::wait_for_boot(env, &self.serial_no, timeout).map_err(RunError::BootFailed)
     }
 
     fn build_apk(
@@ -219,7 +435,15 @@ impl<'a> Device<'a> {
         noise_level: NoiseLevel,
         profile: Profile,
     ) -> Result<(), apk::ApkError> {
-        apk::build(config, env, noise_level, profile, vec![self.target()], true)?;
+        apk::build(
+            config,
+            env,
+            noise_level,
+            profile,
+            vec![self.target()],
+            true,
+            None,
+        )?;
         Ok(())
     }
 
@@ -234,7 +458,11 @@ impl<'a> Device<'a> {
             .into_iter()
             .reduce(last_modified)
             .unwrap();
+        self.install_apk_at(env, &apk_path)
+    }
 
+    fn install_apk_at(&self, env: &Env, apk_path: &Path) -> Result<(), ApkInstallError> {
+        let apk_path = apk_path.to_owned();
         self.adb(env)
             .before_spawn(move |cmd| {
                 cmd.args(["install", "-r"]);
@@ -276,14 +504,30 @@ impl<'a> Device<'a> {
         // and in the case that profile is `Debug` there will be only one path that has the suffix `debug`
         let all_apks_path = Self::all_apks_paths(config, profile, flavor)[0].clone();
         let aab_path = aab::aab_path(config, profile, flavor);
+        self.build_apks_from_aab_at(&aab_path, &all_apks_path, false)
+    }
+
+    fn build_apks_from_aab_at(
+        &self,
+        aab_path: &Path,
+        apks_path: &Path,
+        overwrite: bool,
+    ) -> Result<(), ApksBuildError> {
+        let aab_path = aab_path.to_owned();
+        let apks_path = apks_path.to_owned();
+        let serial_no = self.serial_no.clone();
         bundletool::command()
             .before_spawn(move |cmd| {
                 cmd.args([
                     "build-apks",
                     &format!("--bundle={}", aab_path.to_str().unwrap()),
-                    &format!("--output={}", all_apks_path.to_str().unwrap()),
+                    &format!("--output={}", apks_path.to_str().unwrap()),
                     "--connected-device",
+                    &format!("--device-id={}", serial_no),
                 ]);
+                if overwrite {
+                    cmd.arg("--overwrite");
+                }
                 Ok(())
             })
             .run()
@@ -301,11 +545,18 @@ impl<'a> Device<'a> {
             .into_iter()
             .reduce(last_modified)
             .unwrap();
+        self.install_apks_at(&apks_path)
+    }
+
+    fn install_apks_at(&self, apks_path: &Path) -> Result<(), ApkInstallError> {
+        let apks_path = apks_path.to_owned();
+        let serial_no = self.serial_no.clone();
         bundletool::command()
             .before_spawn(move |cmd| {
                 cmd.args([
                     "install-apks",
                     &format!("--apks={}", apks_path.to_str().unwrap()),
+                    &format!("--device-id={}", serial_no),
                 ]);
 
                 Ok(())
@@ -327,6 +578,68 @@ impl<'a> Device<'a> {
         Ok(())
     }
 
+    fn start_activity(&self, env: &Env, activity: &str) -> std::io::Result<()> {
+        let activity = activity.to_owned();
+        self.adb(env)
+            .before_spawn(move |cmd| {
+                cmd.args(["shell", "am", "start", "-n", &activity]);
+                Ok(())
+            })
+            .dup_stdio()
+            .start()?
+            .wait()?;
+
+        let _ = self.wake_screen(env);
+        Ok(())
+    }
+
+    /// Runs an app management command, which print "Success" or a failure
+    /// reason (and don't always exit with an error on failure).
+    fn app_command(&self, env: &Env, args: &[&str]) -> Result<(), AppCommandError> {
+        let command = args.join(" ");
+        let args = args.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let output = self
+            .adb(env)
+            .before_spawn(move |cmd| {
+                cmd.args(&args);
+                Ok(())
+            })
+            .stdout_capture()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .map_err(|source| AppCommandError::CommandFailed {
+                command: command.clone(),
+                source,
+            })?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.success() && !stdout.contains("Failure") && !stdout.contains("Failed") {
+            Ok(())
+        } else {
+            Err(AppCommandError::Rejected {
+                command,
+                output: [stdout.trim(), stderr.trim()]
+                    .into_iter()
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            })
+        }
+    }
+
+    pub fn uninstall(&self, config: &Config, env: &Env) -> Result<(), AppCommandError> {
+        self.app_command(env, &["uninstall", &config.app_id()])
+    }
+
+    pub fn clear_data(&self, config: &Config, env: &Env) -> Result<(), AppCommandError> {
+        self.app_command(env, &["shell", "pm", "clear", &config.app_id()])
+    }
+
+    pub fn force_stop(&self, config: &Config, env: &Env) -> Result<(), AppCommandError> {
+        self.app_command(env, &["shell", "am", "force-stop", &config.app_id()])
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn run(
         &self,
@@ -338,130 +651,342 @@ impl<'a> Device<'a> {
         build_app_bundle: bool,
         reinstall_deps: bool,
         activity: String,
-    ) -> Result<duct::Handle, RunError> {
+        startup_timeout: Duration,
+        boot_timeout: Duration,
+        logs: LogOptions,
+    ) -> Result<LogcatHandle, RunError> {
+        config
+            .ensure_target_enabled(self.target.name())
+            .map_err(RunError::TargetNotEnabled)?;
         if build_app_bundle {
             bundletool::install(reinstall_deps).map_err(RunError::BundletoolInstallFailed)?;
             self.build_aab(config, env, noise_level, profile)
                 .map_err(RunError::AabError)?;
             self.build_apks_from_aab(config, profile)
                 .map_err(RunError::ApksFromAabBuildFailed)?;
-            if self.serial_no.starts_with("emulator") {
-                self.wait_device_boot(env);
-            }
+            self.wait_for_boot(env, boot_timeout)?;
             self.install_apk_from_aab(config, profile)
                 .map_err(RunError::ApkInstallFailed)?;
         } else {
             self.build_apk(config, env, noise_level, profile)
                 .map_err(RunError::ApkError)?;
-            if self.serial_no.starts_with("emulator") {
-                self.wait_device_boot(env);
-            }
+            self.wait_for_boot(env, boot_timeout)?;
             self.install_apk(config, env, profile)
                 .map_err(RunError::ApkInstallFailed)?;
         }
-        let activity = format!(
-            "{}.{}/{}",
-            config.app().reverse_domain(),
-            config.app().name_snake(),
-            activity
-        );
-        self.adb(env)
-            .before_spawn(move |cmd| {
-                cmd.args(["shell", "am", "start", "-n", &activity]);
-                Ok(())
-            })
-            .dup_stdio()
-            .start()?
-            .wait()?;
+        self.start_activity(env, &format!("{}/{}", config.app_id(), activity))?;
+        Ok(
+            Launch::new(config, noise_level, filter_level, startup_timeout, logs)
+                .logcat(&self.serial_no)
+                .start(env),
+        )
+    }
 
-        let _ = self.wake_screen(env);
+    /// Builds the crate's tests (or benches, for [`CargoMode::Bench`]) for
+    /// this device and runs them on it, passing `args` to each executable.
+    #[allow(clippy::too_many_arguments)]
+    pub fn test(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+        profile: Profile,
+        mode: CargoMode,
+        boot_timeout: Duration,
+        mut args: Vec<String>,
+    ) -> Result<(), TestError> {
+        config
+            .ensure_target_enabled(self.target.name())
+            .map_err(TestError::TargetNotEnabled)?;
+        let executables = self
+            .target
+            .build_tests(config, metadata, env, noise_level, true, profile, mode)
+            .map_err(TestError::BuildFailed)?;
+        if executables.is_empty() {
+            return Err(TestError::NoneBuilt(mode));
+        }
+        if let CargoMode::Bench = mode {
+            // What `cargo bench` passes to the harness
+            args.push("--bench".to_owned());
+        }
+        adb::boot::wait_for_boot(env, &self.serial_no, boot_timeout)
+            .map_err(TestError::BootFailed)?;
+        device_test::run(env, &self.serial_no, self.target, &executables, &args)
+            .map_err(TestError::RunFailed)
+    }
 
-        let filter = format!(
-            "{}:{}",
-            config.app().name(),
-            filter_level
-                .unwrap_or(match noise_level {
-                    NoiseLevel::Polite => FilterLevel::Warn,
-                    NoiseLevel::LoudAndProud => FilterLevel::Info,
-                    NoiseLevel::FranklyQuitePedantic => FilterLevel::Verbose,
-                })
-                .logcat()
-        );
-
-        let stdout = loop {
-            let cmd = duct::cmd(
-                env.platform_tools_path().join("adb"),
-                [
-                    "shell",
-                    "pidof",
-                    "-s",
-                    &format!(
-                        "{}.{}",
-                        config.app().reverse_domain(),
-                        config.app().name_snake(),
-                    ),
-                ],
-            )
-            .vars(env.explicit_env())
-            .stderr_capture()
-            .stdout_capture();
-            let handle = cmd.start()?;
-            if let Ok(out) = handle.wait() {
-                if out.status.success() {
-                    break String::from_utf8_lossy(&out.stdout).into_owned();
-                }
-            }
-            sleep(Duration::from_secs(2));
-        };
-        let pid = stdout.trim().to_string();
-        let mut logcat = duct::cmd(
-            env.platform_tools_path().join("adb"),
-            ["logcat", "-v", "color", "-s", &filter],
-        )
-        .vars(env.explicit_env())
-        .dup_stdio();
+    /// Follows the logs of the app, which is expected to already be installed,
+    /// without building or launching anything.
+    #[allow(clippy::too_many_arguments)]
+    pub fn logcat(
+        &self,
+        config: &Config,
+        env: &Env,
+        noise_level: NoiseLevel,
+        filter_level: Option<FilterLevel>,
+        startup_timeout: Duration,
+        logs: LogOptions,
+    ) -> Result<(), adb::logcat::Error> {
+        Launch::new(config, noise_level, filter_level, startup_timeout, logs)
+            .logcat(&self.serial_no)
+            .follow(env)
+    }
 
-        let logcat_filter_specs = config.logcat_filter_specs().to_vec();
-        logcat = logcat.before_spawn(move |cmd| {
-            if !pid.is_empty() {
-                cmd.args(["--pid", &pid]);
+    /// Installs an already built universal APK or AAB on this device, launches
+    /// `activity` and streams the app's logs, prefixing every line with
+    /// `prefix`, until the app exits or `stop` is set.
+    #[allow(clippy::too_many_arguments)]
+    fn deploy(
+        &self,
+        env: &Env,
+        artifact: &Artifact,
+        activity: &str,
+        launch: &Launch,
+        boot_timeout: Duration,
+        prefix: &str,
+        stop: &AtomicBool,
+    ) -> Result<(), RunError> {
+        self.wait_for_boot(env, boot_timeout)?;
+        match artifact {
+            Artifact::Apk(apk_path) => self
+                .install_apk_at(env, apk_path)
+                .map_err(RunError::ApkInstallFailed)?,
+            Artifact::Aab { aab_path, apks_dir } => {
+                let apks_path = apks_dir.join(format!(
+                    "{}.apks",
+                    self.serial_no
+                        .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+                ));
+                self.build_apks_from_aab_at(aab_path, &apks_path, true)
+                    .map_err(RunError::ApksFromAabBuildFailed)?;
+                self.install_apks_at(&apks_path)
+                    .map_err(RunError::ApkInstallFailed)?;
             }
-            cmd.args(&logcat_filter_specs);
-            Ok(())
-        });
-        logcat.start().map_err(Into::into)
+        }
+        self.start_activity(env, activity)?;
+        launch
+            .logcat(&self.serial_no)
+            .with_prefix(Some(prefix.to_owned()))
+            .follow_until(env, stop)
+            .map_err(RunError::LogcatFailed)
     }
 
     pub fn stacktrace(&self, config: &Config, env: &Env) -> Result<(), StacktraceError> {
-        let jnilib_path = config
-            .app()
-            // ndk-stack can't seem to handle spaces in args, no matter
-            // how I try to quote or escape them... so, instead of
-            // mandating that the entire path not contain spaces, we'll
-            // just use a relative path!
-            .unprefix_path(jnilibs::path(config, *self.target))
-            .expect("developer error: jnilibs subdir not prefixed");
         // -d = print and exit
-        let logcat_command = adb::adb(env, &self.serial_no)
-            .before_spawn(move |cmd| {
-                cmd.args(["logcat", "-d"]);
-                cmd.arg("-sym");
-                cmd.arg(&jnilib_path);
+        let logs = self
+            .adb(env)
+            .before_spawn(|cmd| {
+                cmd.args(["logcat", "-d", "-b", "main", "-b", "crash"]);
+                cmd.args(["-v", "threadtime"]);
                 Ok(())
             })
-            .dup_stdio();
-        let stack_command =
-            duct::cmd::<PathBuf, [String; 0]>(env.ndk.home().join(consts::NDK_STACK), [])
-                .vars(env.explicit_env())
-                .env(
-                    "PATH",
-                    util::prepend_to_path(env.ndk.home().display(), env.path().to_string_lossy()),
-                )
-                .dup_stdio();
-
-        if logcat_command.pipe(stack_command).start()?.wait().is_err() {
+            .stderr_null()
+            .read()
+            .map_err(StacktraceError::LogcatFailed)?;
+        let crashes = symbolicator::parse(&logs);
+        if crashes.is_empty() {
             println!("  -- no stacktrace --");
+            return Ok(());
+        }
+        let symbolicator =
+            Symbolicator::new(&env.ndk, symbolicator::lib_dirs(config, self.target))?;
+        for crash in crashes {
+            println!("{}", symbolicator.symbolicate(&crash)?);
         }
         Ok(())
     }
+
+    /// Prints the symbolicated tombstones on this device (only the newest one
+    /// if `latest` is set), and saves both the original and symbolicated
+    /// versions to `pull_dir` if given.
+    pub fn tombstones(
+        &self,
+        config: &Config,
+        env: &Env,
+        pull_dir: Option<&Path>,
+        latest: bool,
+    ) -> Result<(), TombstonesError> {
+        let mut tombstones = adb::tombstones::list(env, &self.serial_no)?;
+        if tombstones.is_empty() {
+            println!("No tombstones found on {}", self);
+            return Ok(());
+        }
+        if latest {
+            tombstones.truncate(1);
+        }
+        let symbolicator =
+            Symbolicator::new(&env.ndk, symbolicator::lib_dirs(config, self.target))?;
+        let write = |path: PathBuf, contents: &str| {
+            std::fs::write(&path, contents)
+                .map_err(|source| TombstonesError::WriteFailed { path, source })
+        };
+        if let Some(pull_dir) = pull_dir {
+            std::fs::create_dir_all(pull_dir).map_err(|source| TombstonesError::WriteFailed {
+                path: pull_dir.to_owned(),
+                source,
+            })?;
+        }
+        for tombstone in tombstones {
+            let contents = tombstone.read(env, &self.serial_no)?;
+            let backtraces = symbolicator::parse(&contents)
+                .iter()
+                .map(|crash| symbolicator.symbolicate(crash).map(|bt| bt.to_string()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let symbolicated = if backtraces.is_empty() {
+                "  -- no stacktrace --\n".to_owned()
+            } else {
+                backtraces.join("\n")
+            };
+            println!("{}", format!("-- {} --", tombstone.name()).bold());
+            print!("{}", symbolicated);
+            if let Some(pull_dir) = pull_dir {
+                let raw_path = pull_dir.join(tombstone.name());
+                write(raw_path.clone(), &contents)?;
+                write(
+                    pull_dir.join(format!("{}.symbolicated.txt", tombstone.name())),
+                    &symbolicated,
+                )?;
+                println!("Saved to {:?}", raw_path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs an app management command (i.e. [`Device::uninstall`]) on each of
+/// `devices` in turn, collecting failures rather than stopping at the first.
+pub fn app_command_all<'a>(
+    devices: &[Device<'a>],
+    config: &Config,
+    env: &Env,
+    command: impl Fn(&Device<'a>, &Config, &Env) -> Result<(), AppCommandError>,
+) -> Result<(), AppCommandError> {
+    let failures = devices
+        .iter()
+        .filter_map(|device| {
+            command(device, config, env)
+                .err()
+                .map(|err| (device.to_string(), err))
+        })
+        .collect::<Vec<_>>();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(AppCommandError::DevicesFailed {
+            total: devices.len(),
+            failures,
+        })
+    }
+}
+
+/// Builds a single universal APK (or AAB) for all of `devices`, then installs
+/// and launches it on every one of them in parallel. Logs from each device are
+/// prefixed with its name, and failures are collected rather than aborting the
+/// remaining devices.
+#[allow(clippy::too_many_arguments)]
+pub fn run_all(
+    devices: &[Device<'_>],
+    config: &Config,
+    env: &Env,
+    noise_level: NoiseLevel,
+    profile: Profile,
+    filter_level: Option<FilterLevel>,
+    build_app_bundle: bool,
+    reinstall_deps: bool,
+    activity: String,
+    startup_timeout: Duration,
+    boot_timeout: Duration,
+    logs: LogOptions,
+) -> Result<(), RunAllError> {
+    static PREFIX_COLORS: &[Color] = &[
+        Color::BrightCyan,
+        Color::BrightMagenta,
+        Color::BrightYellow,
+        Color::BrightGreen,
+        Color::BrightBlue,
+        Color::BrightRed,
+    ];
+
+    let mut targets = devices.iter().map(Device::target).collect::<Vec<_>>();
+    targets.sort();
+    targets.dedup();
+    for target in &targets {
+        config
+            .ensure_target_enabled(target.name())
+            .map_err(RunAllError::TargetNotEnabled)?;
+    }
+    let artifact = if build_app_bundle {
+        bundletool::install(reinstall_deps).map_err(RunAllError::BundletoolInstallFailed)?;
+        let aab_path = aab::build(config, env, noise_level, profile, targets, false)
+            .map_err(RunAllError::AabError)?
+            .remove(0);
+        let apks_dir = aab_path
+            .parent()
+            .expect("developer error: AAB path had no parent")
+            .to_owned();
+        Artifact::Aab { aab_path, apks_dir }
+    } else {
+        Artifact::Apk(
+            apk::build(config, env, noise_level, profile, targets, false, None)
+                .map_err(RunAllError::ApkError)?
+                .remove(0),
+        )
+    };
+    let activity = format!("{}/{}", config.app_id(), activity);
+    let launch = Launch::new(config, noise_level, filter_level, startup_timeout, logs);
+    // Set once a device fails, so the others stop streaming logs and the
+    // failure gets reported instead of waiting on apps that may never exit
+    let stop = AtomicBool::new(false);
+
+    let failures = std::thread::scope(|scope| {
+        let handles = devices
+            .iter()
+            .enumerate()
+            .map(|(index, device)| {
+                let prefix = format!("[{}]", device)
+                    .color(PREFIX_COLORS[index % PREFIX_COLORS.len()])
+                    .to_string();
+                let (artifact, activity, launch, stop) = (&artifact, &activity, &launch, &stop);
+                (
+                    device,
+                    scope.spawn(move || {
+                        let result = device.deploy(
+                            env,
+                            artifact,
+                            activity,
+                            launch,
+                            boot_timeout,
+                            &prefix,
+                            stop,
+                        );
+                        if result.is_err() {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                        result
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .filter_map(|(device, handle)| {
+                let result = handle.join().unwrap_or_else(|_| {
+                    Err(std::io::Error::other("deploy thread panicked").into())
+                });
+                result
+                    .err()
+                    .map(|err| (crate::device::describe(device), err))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(RunAllError::DevicesFailed {
+            total: devices.len(),
+            failures,
+        })
+    }
 }
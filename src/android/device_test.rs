@@ -0,0 +1,263 @@
+//! Running test and bench executables on a device, like `cargo test` does on
+//! the host. This also backs `cargo-android runner`, which cargo uses to run
+//! anything built for an Android target.
+
+use super::{
+    adb,
+    env::Env,
+    target::{SymlinkLibsError, Target},
+};
+use crate::{
+    target::TargetTrait as _,
+    util::cli::{Report, Reportable},
+    DuctExpressionExt,
+};
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, Read as _},
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
+use thiserror::Error;
+
+static DEVICE_DIR: &str = "/data/local/tmp/cargo-mobile2-test";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    LibsFailed(SymlinkLibsError),
+    #[error("Failed to run `adb {command}`: {source}")]
+    CommandFailed { command: String, source: io::Error },
+    #[error("`adb {command}` exited with {status}")]
+    Rejected { command: String, status: String },
+    #[error("{} failed: {}", if .0.len() == 1 { "1 test executable" } else { "Some test executables" }, .0.join(", "))]
+    TestsFailed(Vec<String>),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::LibsFailed(err) => err.report(),
+            Self::CommandFailed { .. } | Self::Rejected { .. } => {
+                Report::error("Failed to push tests to device", self)
+            }
+            Self::TestsFailed(_) => Report::error("Tests failed on device", self),
+        }
+    }
+}
+
+fn adb_command(env: &Env, serial_no: &str, args: Vec<String>) -> Result<(), Error> {
+    let command = args.join(" ");
+    let status = adb::adb(env, serial_no)
+        .before_spawn(move |cmd| {
+            cmd.args(&args);
+            Ok(())
+        })
+        .stdout_null()
+        .unchecked()
+        .run()
+        .map_err(|source| Error::CommandFailed {
+            command: command.clone(),
+            source,
+        })?
+        .status;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Rejected {
+            command,
+            status: status.to_string(),
+        })
+    }
+}
+
+/// Quotes an argument for the device's shell.
+fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// The ABI an ELF file was built for, going by the machine in its header.
+fn elf_abi(path: &Path) -> Option<&'static str> {
+    let mut header = [0; 20];
+    File::open(path).ok()?.read_exact(&mut header).ok()?;
+    if &header[..4] != b"\x7fELF" {
+        return None;
+    }
+    match u16::from_le_bytes([header[0x12], header[0x13]]) {
+        0x28 => Some("armeabi-v7a"),
+        0xb7 => Some("arm64-v8a"),
+        0x03 => Some("x86"),
+        0x3e => Some("x86_64"),
+        0xf3 => Some("riscv64"),
+        _ => None,
+    }
+}
+
+/// The target `executable` was built for, going by the `target/<triple>` dir
+/// cargo put it in, or failing that, its ELF header.
+pub fn executable_target(executable: &Path) -> Option<&'static Target<'static>> {
+    executable
+        .components()
+        .find_map(|component| {
+            Target::all()
+                .values()
+                .find(|target| component.as_os_str() == target.triple)
+        })
+        .or_else(|| elf_abi(executable).and_then(Target::for_abi))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .expect("developer error: executable had no file name")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Pushes `executables` along with the shared libraries they need.
+pub fn push(
+    env: &Env,
+    serial_no: &str,
+    target: &Target,
+    executables: &[PathBuf],
+) -> Result<(), Error> {
+    let mut libs = BTreeSet::new();
+    for executable in executables {
+        libs.extend(
+            target
+                .bundled_libs(&env.ndk, executable)
+                .map_err(Error::LibsFailed)?,
+        );
+    }
+    adb_command(
+        env,
+        serial_no,
+        vec![
+            "shell".to_owned(),
+            format!("rm -rf {dir} && mkdir -p {dir}", dir = DEVICE_DIR),
+        ],
+    )?;
+    for path in libs.iter().chain(executables) {
+        log::info!("pushing {:?} to {}", path, DEVICE_DIR);
+        adb_command(
+            env,
+            serial_no,
+            vec![
+                "push".to_owned(),
+                path.to_string_lossy().into_owned(),
+                format!("{}/{}", DEVICE_DIR, file_name(path)),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs a pushed executable, streaming its output, and returns its exit
+/// status, which `adb shell` passes through.
+pub fn run_executable(
+    env: &Env,
+    serial_no: &str,
+    executable: &Path,
+    args: &[String],
+) -> Result<ExitStatus, Error> {
+    let name = file_name(executable);
+    let script = format!(
+        "cd {dir} && chmod 755 {name} && LD_LIBRARY_PATH={dir} RUST_BACKTRACE=${{RUST_BACKTRACE:-1}} ./{name} {args}",
+        dir = DEVICE_DIR,
+        name = quote(&name),
+        args = args
+            .iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    adb::adb(env, serial_no)
+        .before_spawn(move |cmd| {
+            cmd.args(["shell", &script]);
+            Ok(())
+        })
+        .dup_stdio()
+        .unchecked()
+        .run()
+        .map(|output| output.status)
+        .map_err(|source| Error::CommandFailed {
+            command: format!("shell ./{}", name),
+            source,
+        })
+}
+
+/// Pushes and runs each of `executables` with `args`. Every executable runs
+/// even if an earlier one fails.
+pub fn run(
+    env: &Env,
+    serial_no: &str,
+    target: &Target,
+    executables: &[PathBuf],
+    args: &[String],
+) -> Result<(), Error> {
+    push(env, serial_no, target, executables)?;
+    let mut failed = Vec::new();
+    for executable in executables {
+        println!("     Running {} on {}", file_name(executable), serial_no);
+        if !run_executable(env, serial_no, executable, args)?.success() {
+            failed.push(file_name(executable));
+        }
+    }
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::TestsFailed(failed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        arg,
+        expected,
+        case("--nocapture", "'--nocapture'"),
+        case("it's", r"'it'\''s'"),
+        case("'", r"''\'''"),
+        case("a b; rm -rf /", "'a b; rm -rf /'"),
+        case("", "''")
+    )]
+    fn test_quote(arg: &str, expected: &str) {
+        assert_eq!(quote(arg), expected);
+    }
+
+    #[rstest(
+        executable,
+        abi,
+        case(
+            "target/aarch64-linux-android/debug/deps/app-0123456789abcdef",
+            Some("arm64-v8a")
+        ),
+        case(
+            "/src/app/target/x86_64-linux-android/release/examples/demo",
+            Some("x86_64")
+        ),
+        case("target/debug/deps/app-0123456789abcdef", None)
+    )]
+    fn test_executable_target_from_path(executable: &str, abi: Option<&str>) {
+        assert_eq!(
+            executable_target(Path::new(executable)).map(|target| target.abi),
+            abi
+        );
+    }
+
+    #[test]
+    fn test_executable_target_from_elf() {
+        let mut header = vec![0; 64];
+        header[..4].copy_from_slice(b"\x7fELF");
+        header[0x12..0x14].copy_from_slice(&0xb7u16.to_le_bytes());
+        let path =
+            std::env::temp_dir().join(format!("cargo-mobile2-runner-{}", std::process::id()));
+        std::fs::write(&path, header).unwrap();
+        let target = executable_target(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(target.map(|target| target.abi), Some("arm64-v8a"));
+    }
+}
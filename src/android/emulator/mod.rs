@@ -1,12 +1,158 @@
-mod avd_list;
+pub mod avd_list;
 
-use std::{fmt::Display, path::PathBuf};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
 pub use avd_list::avd_list;
 use duct::Handle;
+use thiserror::Error;
 
-use super::env::Env;
-use crate::{env::ExplicitEnv, DuctExpressionExt};
+use super::{adb, env::Env};
+use crate::{
+    env::ExplicitEnv,
+    util::cli::{Report, Reportable},
+    DuctExpressionExt,
+};
+
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// The emulator only accepts even console ports in this range
+const CONSOLE_PORTS: std::ops::RangeInclusive<u16> = 5554..=5584;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Couldn't find `{name}` in the Android SDK; install the Android SDK Command-line Tools (tried {tried:?})")]
+    SdkToolMissing {
+        name: &'static str,
+        tried: Vec<PathBuf>,
+    },
+    #[error("Failed to install system image {package:?}; if you haven't accepted the Android SDK licenses yet, run `sdkmanager --licenses` and try again: {source}")]
+    SystemImageInstallFailed {
+        package: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to create AVD {name:?}: {source}")]
+    CreateFailed {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to delete AVD {name:?}: {source}")]
+    DeleteFailed {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error("No AVD named {0:?} exists")]
+    NotFound(String),
+    #[error(transparent)]
+    AvdListFailed(#[from] avd_list::Error),
+    #[error(transparent)]
+    DeviceListFailed(#[from] adb::device_list::Error),
+    #[error("Every emulator console port is in use")]
+    NoFreePort,
+    #[error("Failed to start emulator {name:?}: {source}")]
+    StartFailed {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error(
+        "Emulator {name:?} exited before it finished booting ({status}); its output was:\n{output}"
+    )]
+    ExitedEarly {
+        name: String,
+        status: ExitStatus,
+        output: String,
+    },
+    #[error(transparent)]
+    BootFailed(#[from] adb::boot::Error),
+    #[error("Emulator {0:?} isn't running")]
+    NotRunning(String),
+    #[error("Failed to stop emulator {name:?}: {source}")]
+    StopFailed {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error("Emulator {name:?} was still running {timeout:?} after being told to stop")]
+    StopTimedOut { name: String, timeout: Duration },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::SdkToolMissing { .. } => Report::action_request("Android SDK tool missing", self),
+            Self::SystemImageInstallFailed { .. } => {
+                Report::action_request("Failed to install system image", self)
+            }
+            Self::CreateFailed { .. } => Report::error("Failed to create AVD", self),
+            Self::DeleteFailed { .. } => Report::error("Failed to delete AVD", self),
+            Self::NotFound(_) => Report::error("AVD not found", self),
+            Self::AvdListFailed(err) => Report::error("Failed to list AVDs", err),
+            Self::DeviceListFailed(err) => err.report(),
+            Self::NoFreePort | Self::StartFailed { .. } | Self::ExitedEarly { .. } => {
+                Report::error("Failed to start emulator", self)
+            }
+            Self::BootFailed(err) => err.report(),
+            Self::NotRunning(_) | Self::StopFailed { .. } | Self::StopTimedOut { .. } => {
+                Report::error("Failed to stop emulator", self)
+            }
+        }
+    }
+}
+
+/// Finds a tool from the Android SDK Command-line Tools (or the legacy SDK
+/// Tools).
+fn sdk_tool(env: &Env, name: &'static str) -> Result<PathBuf, Error> {
+    let file_name = if cfg!(windows) {
+        format!("{}.bat", name)
+    } else {
+        name.to_owned()
+    };
+    let home = Path::new(env.android_home());
+    let mut tried = vec![home.join("cmdline-tools/latest/bin").join(&file_name)];
+    if let Ok(entries) = std::fs::read_dir(home.join("cmdline-tools")) {
+        let mut versions = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path().join("bin").join(&file_name))
+            .collect::<Vec<_>>();
+        versions.sort();
+        tried.extend(versions.into_iter().rev());
+    }
+    tried.push(home.join("tools/bin").join(&file_name));
+    tried
+        .iter()
+        .find(|path| path.is_file())
+        .cloned()
+        .ok_or(Error::SdkToolMissing { name, tried })
+}
+
+/// Installs a system image (i.e. `system-images;android-34;google_apis;x86_64`)
+/// through `sdkmanager` if it isn't installed already. Any license prompts are
+/// left to the user; without a terminal to answer them on, the licenses have
+/// to have been accepted through `sdkmanager --licenses` beforehand.
+fn install_system_image(env: &Env, package: &str, non_interactive: bool) -> Result<(), Error> {
+    let image_dir = package
+        .split(';')
+        .fold(PathBuf::from(env.android_home()), |dir, part| {
+            dir.join(part)
+        });
+    if image_dir.is_dir() {
+        return Ok(());
+    }
+    let mut cmd = duct::cmd(sdk_tool(env, "sdkmanager")?, [package]).vars(env.explicit_env());
+    if non_interactive {
+        cmd = cmd.stdin_null();
+    }
+    cmd.run()
+        .map_err(|source| Error::SystemImageInstallFailed {
+            package: package.to_owned(),
+            source,
+        })?;
+    Ok(())
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Emulator {
@@ -24,17 +170,56 @@ impl Emulator {
         Self { name }
     }
 
+    /// Looks up an existing AVD by name.
+    pub fn find(env: &Env, name: &str) -> Result<Self, Error> {
+        avd_list(env)?
+            .into_iter()
+            .find(|emulator| emulator.name == name)
+            .ok_or_else(|| Error::NotFound(name.to_owned()))
+    }
+
+    /// Creates an AVD from a system image, installing the image first if
+    /// needed. `device` is a hardware profile from `avdmanager list device`.
+    pub fn create(
+        env: &Env,
+        name: &str,
+        package: &str,
+        device: Option<&str>,
+        force: bool,
+        non_interactive: bool,
+    ) -> Result<Self, Error> {
+        install_system_image(env, package, non_interactive)?;
+        let mut args = vec!["create", "avd", "--name", name, "--package", package];
+        if let Some(device) = device {
+            args.extend(["--device", device]);
+        }
+        if force {
+            args.push("--force");
+        }
+        duct::cmd(sdk_tool(env, "avdmanager")?, args)
+            .vars(env.explicit_env())
+            // Declines creating a custom hardware profile
+            .stdin_bytes("no\n")
+            .run()
+            .map_err(|source| Error::CreateFailed {
+                name: name.to_owned(),
+                source,
+            })?;
+        Ok(Self::new(name.to_owned()))
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    fn emulator_path(env: &Env) -> PathBuf {
+        PathBuf::from(env.android_home()).join("emulator/emulator")
+    }
+
     fn command(&self, env: &Env) -> duct::Expression {
-        duct::cmd(
-            PathBuf::from(env.android_home()).join("emulator/emulator"),
-            ["-avd", &self.name],
-        )
-        .vars(env.explicit_env())
-        .dup_stdio()
+        duct::cmd(Self::emulator_path(env), ["-avd", &self.name])
+            .vars(env.explicit_env())
+            .dup_stdio()
     }
 
     pub fn start(&self, env: &Env) -> Result<Handle, std::io::Error> {
@@ -45,4 +230,145 @@ impl Emulator {
         self.command(env).run_and_detach()?;
         Ok(())
     }
+
+    /// Starts the emulator in the background on a free console port, returning
+    /// the serial it'll show up as in `adb devices` and the process, whose
+    /// output goes to the returned log file.
+    fn start_on_free_port(
+        &self,
+        env: &Env,
+        headless: bool,
+    ) -> Result<(String, Handle, PathBuf), Error> {
+        let serials = adb::serial_list_any_state(env)?;
+        let port = CONSOLE_PORTS
+            .step_by(2)
+            .find(|port| !serials.contains(&format!("emulator-{}", port)))
+            .ok_or(Error::NoFreePort)?;
+        let mut args = vec![
+            "-avd".to_owned(),
+            self.name.clone(),
+            "-port".to_owned(),
+            port.to_string(),
+        ];
+        if headless {
+            args.extend(["-no-window", "-no-audio", "-no-snapshot-save"].map(String::from));
+        }
+        let serial_no = format!("emulator-{}", port);
+        // A file rather than a pipe, since the emulator outlives us
+        let log_path = std::env::temp_dir().join(format!("cargo-mobile2-{}.log", serial_no));
+        let handle = duct::cmd(Self::emulator_path(env), args)
+            .vars(env.explicit_env())
+            .stdin_null()
+            .stderr_to_stdout()
+            .stdout_path(&log_path)
+            .unchecked()
+            .start_in_new_session()
+            .map_err(|source| Error::StartFailed {
+                name: self.name.clone(),
+                source,
+            })?;
+        Ok((serial_no, handle, log_path))
+    }
+
+    /// Starts the emulator and waits for it to boot, returning its serial. If
+    /// the AVD is already running, that instance is waited on instead.
+    pub fn boot(&self, env: &Env, headless: bool, timeout: Duration) -> Result<String, Error> {
+        if let Some(serial_no) = self.running_serial(env)? {
+            adb::boot::wait_for_boot(env, &serial_no, timeout)?;
+            return Ok(serial_no);
+        }
+        let (serial_no, handle, log_path) = self.start_on_free_port(env, headless)?;
+        // Only failing counts, in case the launcher hands off to a process
+        // of its own and exits
+        let booted = adb::boot::wait_for_boot_while(
+            env,
+            &serial_no,
+            timeout,
+            || !matches!(handle.try_wait(), Ok(Some(output)) if !output.status.success()),
+        )?;
+        if booted {
+            return Ok(serial_no);
+        }
+        let status = handle
+            .wait()
+            .map_err(|source| Error::StartFailed {
+                name: self.name.clone(),
+                source,
+            })?
+            .status;
+        Err(Error::ExitedEarly {
+            name: self.name.clone(),
+            status,
+            output: std::fs::read_to_string(&log_path)
+                .unwrap_or_default()
+                .trim_end()
+                .to_owned(),
+        })
+    }
+
+    /// The serial of the running instance of this AVD, if there is one.
+    pub fn running_serial(&self, env: &Env) -> Result<Option<String>, Error> {
+        Ok(adb::serial_list_any_state(env)?
+            .into_iter()
+            .filter(|serial_no| serial_no.starts_with("emulator-"))
+            .find(|serial_no| {
+                adb::adb(env, serial_no)
+                    .before_spawn(|cmd| {
+                        cmd.args(["emu", "avd", "name"]);
+                        Ok(())
+                    })
+                    .stderr_null()
+                    .read()
+                    .map(|output| output.lines().next().map(str::trim) == Some(&self.name))
+                    .unwrap_or(false)
+            }))
+    }
+
+    /// Shuts the emulator down through its console and waits for it to
+    /// disappear from `adb devices`.
+    pub fn stop(&self, env: &Env) -> Result<(), Error> {
+        let serial_no = self
+            .running_serial(env)?
+            .ok_or_else(|| Error::NotRunning(self.name.clone()))?;
+        adb::adb(env, &serial_no)
+            .before_spawn(|cmd| {
+                cmd.args(["emu", "kill"]);
+                Ok(())
+            })
+            .stdout_null()
+            .run()
+            .map_err(|source| Error::StopFailed {
+                name: self.name.clone(),
+                source,
+            })?;
+        let start = Instant::now();
+        while adb::serial_list_any_state(env)?.contains(&serial_no) {
+            if start.elapsed() >= STOP_TIMEOUT {
+                return Err(Error::StopTimedOut {
+                    name: self.name.clone(),
+                    timeout: STOP_TIMEOUT,
+                });
+            }
+            sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Deletes the AVD, stopping it first if it's running.
+    pub fn delete(self, env: &Env) -> Result<(), Error> {
+        if self.running_serial(env)?.is_some() {
+            self.stop(env)?;
+        }
+        duct::cmd(
+            sdk_tool(env, "avdmanager")?,
+            ["delete", "avd", "--name", &self.name],
+        )
+        .vars(env.explicit_env())
+        .run()
+        .map_err(|source| Error::DeleteFailed {
+            name: self.name.clone(),
+            source,
+        })?;
+        Ok(())
+    }
 }
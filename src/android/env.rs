@@ -1,15 +1,21 @@
 use super::{
+    config::Config,
     ndk,
     source_props::{self, SourceProps},
 };
 use crate::{
     env::{Error as CoreError, ExplicitEnv},
     os::Env as CoreEnv,
-    util::cli::{Report, Reportable},
+    util::{
+        self,
+        cli::{Report, Reportable},
+    },
 };
+use once_cell_regex::regex;
 use std::{
     collections::HashMap,
     ffi::OsString,
+    fmt::{self, Display},
     path::{Path, PathBuf},
 };
 use thiserror::Error;
@@ -19,8 +25,11 @@ pub enum Error {
     #[error(transparent)]
     CoreEnvError(#[from] CoreError),
     // TODO: we should be nice and provide a platform-specific suggestion
-    #[error("Have you installed the Android SDK? The `ANDROID_HOME` environment variable isn't set, and is required: {0}")]
-    AndroidHomeNotSet(#[from] std::env::VarError),
+    #[error(
+        "Have you installed the Android SDK? The `ANDROID_HOME` environment variable isn't set, and the SDK wasn't found in any of the usual places:{}",
+        .tried.iter().map(|path| format!("\n  {:?}", path)).collect::<String>()
+    )]
+    SdkNotFound { tried: Vec<PathBuf> },
     #[error("Have you installed the Android SDK? The `ANDROID_HOME` environment variable is set, but doesn't point to an existing directory.")]
     AndroidHomeNotADir,
     #[error(transparent)]
@@ -43,57 +52,185 @@ impl Error {
     }
 }
 
+/// Where the SDK's location came from.
+#[derive(Clone, Debug)]
+pub enum SdkSource {
+    AndroidHome,
+    AndroidSdkRoot,
+    LocalProperties(PathBuf),
+    AndroidStudio(PathBuf),
+    DefaultLocation,
+}
+
+impl Display for SdkSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AndroidHome => write!(f, "from `ANDROID_HOME`"),
+            Self::AndroidSdkRoot => write!(f, "from `ANDROID_SDK_ROOT`"),
+            Self::LocalProperties(path) => write!(f, "from `sdk.dir` in {:?}", path),
+            Self::AndroidStudio(path) => write!(f, "from Android Studio's config at {:?}", path),
+            Self::DefaultLocation => write!(f, "in the default location"),
+        }
+    }
+}
+
+/// Where Android Studio installs the SDK by default.
+#[cfg(target_os = "macos")]
+fn default_sdk_dir() -> Option<PathBuf> {
+    util::home_dir()
+        .ok()
+        .map(|home| home.join("Library/Android/sdk"))
+}
+
+#[cfg(target_os = "linux")]
+fn default_sdk_dir() -> Option<PathBuf> {
+    util::home_dir().ok().map(|home| home.join("Android/Sdk"))
+}
+
+#[cfg(windows)]
+fn default_sdk_dir() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Android\\Sdk"))
+}
+
+/// Where Android Studio keeps its per-version config directories.
+#[cfg(target_os = "macos")]
+fn studio_config_dir() -> Option<PathBuf> {
+    util::home_dir()
+        .ok()
+        .map(|home| home.join("Library/Application Support/Google"))
+}
+
+#[cfg(target_os = "linux")]
+fn studio_config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| util::home_dir().ok().map(|home| home.join(".config")))
+        .map(|dir| dir.join("Google"))
+}
+
+#[cfg(windows)]
+fn studio_config_dir() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Google"))
+}
+
+/// `sdk.dir` from a `local.properties`, which Android Studio writes when it
+/// opens the project.
+fn local_properties_sdk_dir(path: &Path) -> Option<PathBuf> {
+    let file = std::fs::File::open(path).ok()?;
+    java_properties::read(file)
+        .ok()?
+        .remove("sdk.dir")
+        .map(PathBuf::from)
+}
+
+/// The SDK path from the newest Android Studio config that has one, along
+/// with the file it came from.
+fn studio_sdk_dir() -> Option<(PathBuf, PathBuf)> {
+    let mut configs = std::fs::read_dir(studio_config_dir()?)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("AndroidStudio"))
+        })
+        .map(|path| path.join("options/other.xml"))
+        .collect::<Vec<_>>();
+    // Version numbers in the directory names sort correctly as strings
+    configs.sort();
+    configs.into_iter().rev().find_map(|path| {
+        let contents = std::fs::read_to_string(&path).ok()?;
+        let sdk_dir = parse_studio_sdk_path(&contents)?;
+        Some((sdk_dir, path))
+    })
+}
+
+fn parse_studio_sdk_path(other_xml: &str) -> Option<PathBuf> {
+    let value = regex!(r#"name="android\.sdk\.path"\s+value="([^"]+)""#)
+        .captures(other_xml)?
+        .get(1)?
+        .as_str();
+    Some(match value.strip_prefix("$USER_HOME$") {
+        Some(rest) => util::home_dir()
+            .ok()?
+            .join(rest.trim_start_matches(['/', '\\'])),
+        None => PathBuf::from(value),
+    })
+}
+
+/// Looks for the SDK, in order of how explicitly each place was configured.
+fn find_sdk(config: Option<&Config>) -> Result<(PathBuf, SdkSource), Error> {
+    let mut tried = Vec::new();
+    let mut try_dir = |path: PathBuf, source: SdkSource| {
+        if path.is_dir() {
+            Some((path, source))
+        } else {
+            tried.push(path);
+            None
+        }
+    };
+    let android_home = std::env::var_os("ANDROID_HOME").map(PathBuf::from);
+    if let Some(found) = android_home
+        .clone()
+        .and_then(|path| try_dir(path, SdkSource::AndroidHome))
+    {
+        return Ok(found);
+    }
+    if let Some(found) = std::env::var_os("ANDROID_SDK_ROOT")
+        .map(PathBuf::from)
+        .and_then(|path| try_dir(path, SdkSource::AndroidSdkRoot))
+    {
+        log::warn!(
+            "`ANDROID_HOME` isn't set; falling back to `ANDROID_SDK_ROOT`, which is deprecated"
+        );
+        return Ok(found);
+    }
+    if android_home.is_some() {
+        return Err(Error::AndroidHomeNotADir);
+    }
+    let local_properties = config.map(|config| config.project_dir().join("local.properties"));
+    let probes = local_properties
+        .and_then(|path| {
+            local_properties_sdk_dir(&path).map(|dir| (dir, SdkSource::LocalProperties(path)))
+        })
+        .into_iter()
+        .chain(studio_sdk_dir().map(|(dir, path)| (dir, SdkSource::AndroidStudio(path))))
+        .chain(default_sdk_dir().map(|dir| (dir, SdkSource::DefaultLocation)));
+    for (path, source) in probes {
+        if let Some((path, source)) = try_dir(path, source) {
+            log::info!("`ANDROID_HOME` isn't set; using {:?} {}", path, source);
+            return Ok((path, source));
+        }
+    }
+    Err(Error::SdkNotFound { tried })
+}
+
 #[derive(Debug, Clone)]
 pub struct Env {
     pub base: CoreEnv,
     android_home: PathBuf,
+    sdk_source: SdkSource,
     pub ndk: ndk::Env,
 }
 
 impl Env {
-    pub fn new() -> Result<Self, Error> {
-        Self::from_env(CoreEnv::new()?)
-    }
-
-    pub fn from_env(base: CoreEnv) -> Result<Self, Error> {
-        let android_home = std::env::var("ANDROID_HOME")
-            .map_err(Error::AndroidHomeNotSet)
-            .map(PathBuf::from)
-            .and_then(|android_home| {
-                if android_home.is_dir() {
-                    Ok(android_home)
-                } else {
-                    Err(Error::AndroidHomeNotADir)
-                }
-            })
-            .or_else(|err| {
-                if let Some(sdk_root) = std::env::var("ANDROID_SDK_ROOT")
-                    .ok()
-                    .map(PathBuf::from)
-                    .filter(|sdk_root| sdk_root.is_dir())
-                {
-                    log::warn!("`ANDROID_HOME` isn't set; falling back to `ANDROID_SDK_ROOT`, which is deprecated");
-                    Ok(sdk_root)
-                } else {
-                    Err(err)
-                }
-            })
-            .or_else(|err| {
-                if let Some(sdk_root) = std::env::var("ANDROID_SDK_ROOT")
-                    .ok()
-                    .map(PathBuf::from)
-                    .filter(|sdk_root| sdk_root.is_dir())
-                {
-                    log::warn!("`ANDROID_HOME` isn't set; falling back to `ANDROID_SDK_ROOT`, which is deprecated");
-                    Ok(sdk_root)
-                } else {
-                    Err(err)
-                }
-            })?;
+    /// `config` is the project's, if there is one, which can point at the
+    /// SDK and pin the NDK.
+    pub fn new(config: Option<&Config>) -> Result<Self, Error> {
+        Self::from_env(CoreEnv::new()?, config)
+    }
+
+    pub fn from_env(base: CoreEnv, config: Option<&Config>) -> Result<Self, Error> {
+        let (android_home, sdk_source) = find_sdk(config)?;
+        let ndk = ndk::Env::new(
+            &android_home,
+            config.and_then(|config| config.ndk_version()),
+        )?;
         Ok(Self {
             base,
             android_home,
-            ndk: ndk::Env::new()?,
+            sdk_source,
+            ndk,
         })
     }
 
@@ -105,10 +242,41 @@ impl Env {
         self.android_home.as_path().to_str().unwrap()
     }
 
+    pub fn sdk_source(&self) -> &SdkSource {
+        &self.sdk_source
+    }
+
+    /// Whether the SDK Platform for an API level is installed.
+    pub fn platform_installed(&self, api_level: u32) -> bool {
+        self.android_home
+            .join("platforms")
+            .join(format!("android-{}", api_level))
+            .is_dir()
+    }
+
     pub fn platform_tools_path(&self) -> PathBuf {
         PathBuf::from(&self.android_home).join("platform-tools")
     }
 
+    /// The newest installed build-tools, i.e. `build-tools/34.0.0`.
+    pub fn build_tools_path(&self) -> Option<PathBuf> {
+        let version = |path: &Path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.split('-').next())
+                .map(|core| {
+                    core.split('.')
+                        .map(|part| part.parse::<u32>().unwrap_or_default())
+                        .collect::<Vec<_>>()
+                })
+        };
+        std::fs::read_dir(self.android_home.join("build-tools"))
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir())
+            .max_by_key(|path| version(path))
+    }
+
     pub fn sdk_version(&self) -> Result<source_props::Revision, source_props::Error> {
         SourceProps::from_path(Path::new(self.android_home()).join("tools/source.properties"))
             .map(|props| props.pkg.revision)
@@ -129,3 +297,29 @@ impl ExplicitEnv for Env {
         envs
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_studio_sdk_path() {
+        let other_xml = r#"<application>
+  <component name="PropertiesComponent">
+    <property name="android.sdk.path" value="/opt/android-sdk" />
+    <property name="settings.editor.selected.configurable" value="AndroidSdkUpdater" />
+  </component>
+</application>"#;
+        assert_eq!(
+            parse_studio_sdk_path(other_xml),
+            Some(PathBuf::from("/opt/android-sdk"))
+        );
+        assert_eq!(
+            parse_studio_sdk_path(
+                r#"<property name="android.sdk.path" value="$USER_HOME$/Android/Sdk" />"#
+            ),
+            Some(util::home_dir().unwrap().join("Android/Sdk"))
+        );
+        assert_eq!(parse_studio_sdk_path("<application />"), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! Generating launcher icons for every density from a single source image.
+
+use super::{config::Config, project::TEMPLATE_PACK};
+use crate::{
+    templating::Pack,
+    util::cli::{Report, Reportable},
+};
+use image::{codecs::webp::WebPEncoder, imageops, ImageEncoder as _, ImageError, Rgba, RgbaImage};
+use sha2::{Digest as _, Sha256};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+pub static DEFAULT_BACKGROUND: &str = "#FFFFFF";
+static RES_DIR: &str = "app/src/main/res";
+// Hashes of the icons we generated last time, so we can tell them apart from
+// ones the user replaced
+static RECORD_FILE: &str = ".generated-icons";
+static ADAPTIVE_ICON_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<adaptive-icon xmlns:android="http://schemas.android.com/apk/res/android">
+    <background android:drawable="@mipmap/ic_launcher_background" />
+    <foreground android:drawable="@mipmap/ic_launcher_foreground" />
+</adaptive-icon>
+"#;
+
+/// Density buckets and their scale relative to mdpi.
+static DENSITIES: &[(&str, f32)] = &[
+    ("mdpi", 1.0),
+    ("hdpi", 1.5),
+    ("xhdpi", 2.0),
+    ("xxhdpi", 3.0),
+    ("xxxhdpi", 4.0),
+];
+const LEGACY_DP: f32 = 48.0;
+const ADAPTIVE_DP: f32 = 108.0;
+// Launchers can mask away anything outside of this diameter
+const SAFE_ZONE_DP: f32 = 66.0;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to load {path:?}: {source}")]
+    LoadFailed { path: PathBuf, source: ImageError },
+    #[error("{0:?} isn't a `#RRGGBB` color")]
+    BackgroundInvalid(String),
+    #[error("Failed to encode {path:?}: {source}")]
+    EncodeFailed { path: PathBuf, source: ImageError },
+    #[error("Failed to write {path:?}: {source}")]
+    WriteFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        Report::error("Failed to generate launcher icons", self)
+    }
+}
+
+pub fn parse_color(color: &str) -> Result<Rgba<u8>, Error> {
+    let invalid = || Error::BackgroundInvalid(color.to_owned());
+    let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, 0xff]))
+}
+
+/// Scales `source` to fit within `content` pixels, centered on a transparent
+/// `canvas`-sized square.
+fn fit(source: &RgbaImage, canvas: u32, content: u32) -> RgbaImage {
+    let scale = content as f32 / source.width().max(source.height()) as f32;
+    let width = ((source.width() as f32 * scale).round() as u32).max(1);
+    let height = ((source.height() as f32 * scale).round() as u32).max(1);
+    let scaled = imageops::resize(source, width, height, imageops::FilterType::Lanczos3);
+    let mut image = RgbaImage::new(canvas, canvas);
+    imageops::overlay(
+        &mut image,
+        &scaled,
+        ((canvas - width) / 2).into(),
+        ((canvas - height) / 2).into(),
+    );
+    image
+}
+
+fn encode(image: &RgbaImage, path: &Path) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    WebPEncoder::new_lossless(&mut bytes)
+        .write_image(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|source| Error::EncodeFailed {
+            path: path.to_owned(),
+            source,
+        })?;
+    Ok(bytes)
+}
+
+fn hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+/// Icons generated from a source image, keyed by their path relative to the
+/// `res` directory.
+fn render(source: &RgbaImage, background: Rgba<u8>) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+    let mut files = BTreeMap::new();
+    for (density, scale) in DENSITIES {
+        let dir = format!("mipmap-{}", density);
+        let px = |dp: f32| (dp * scale).round() as u32;
+        let legacy = fit(source, px(LEGACY_DP), px(LEGACY_DP));
+        let foreground = fit(source, px(ADAPTIVE_DP), px(SAFE_ZONE_DP));
+        let background = RgbaImage::from_pixel(px(ADAPTIVE_DP), px(ADAPTIVE_DP), background);
+        for (name, image) in [
+            ("ic_launcher.webp", legacy),
+            ("ic_launcher_foreground.webp", foreground),
+            ("ic_launcher_background.webp", background),
+        ] {
+            let path = format!("{}/{}", dir, name);
+            let bytes = encode(&image, Path::new(&path))?;
+            files.insert(path, bytes);
+        }
+    }
+    files.insert(
+        "mipmap-anydpi-v26/ic_launcher.xml".to_owned(),
+        ADAPTIVE_ICON_XML.as_bytes().to_vec(),
+    );
+    Ok(files)
+}
+
+fn read_record(project_dir: &Path) -> BTreeMap<String, String> {
+    fs::read_to_string(project_dir.join(RECORD_FILE))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.rsplit_once(' '))
+        .map(|(path, hash)| (path.to_owned(), hash.to_owned()))
+        .collect()
+}
+
+/// What happened to each icon.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub written: Vec<PathBuf>,
+    /// Icons that were customized, and so were left alone.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Writes launcher icons for every density into the generated project.
+/// Existing icons are only replaced if they're the template's stock icons or
+/// ones we generated before, unless `force` is set.
+pub fn generate(
+    config: &Config,
+    source_path: &Path,
+    background: Rgba<u8>,
+    force: bool,
+) -> Result<Summary, Error> {
+    let source = image::open(source_path)
+        .map_err(|source| Error::LoadFailed {
+            path: source_path.to_owned(),
+            source,
+        })?
+        .into_rgba8();
+    let project_dir = config.project_dir();
+    let res_dir = project_dir.join(RES_DIR);
+    let stock_res_dir = Pack::lookup_platform(TEMPLATE_PACK)
+        .ok()
+        .map(|pack| pack.expect_local().join(RES_DIR));
+    let mut record = read_record(&project_dir);
+    let mut summary = Summary::default();
+    for (rel_path, bytes) in render(&source, background)? {
+        let path = res_dir.join(&rel_path);
+        let replaceable = match fs::read(&path) {
+            Ok(existing) => {
+                force
+                    || record.get(&rel_path) == Some(&hash(&existing))
+                    || stock_res_dir
+                        .as_ref()
+                        .and_then(|dir| fs::read(dir.join(&rel_path)).ok())
+                        .is_some_and(|stock| stock == existing)
+            }
+            Err(_) => true,
+        };
+        if !replaceable {
+            summary.skipped.push(path);
+            continue;
+        }
+        path.parent()
+            .map(fs::create_dir_all)
+            .transpose()
+            .and_then(|_| fs::write(&path, &bytes))
+            .map_err(|source| Error::WriteFailed {
+                path: path.clone(),
+                source,
+            })?;
+        record.insert(rel_path, hash(&bytes));
+        summary.written.push(path);
+    }
+    let record_path = project_dir.join(RECORD_FILE);
+    fs::write(
+        &record_path,
+        record
+            .iter()
+            .map(|(path, hash)| format!("{} {}\n", path, hash))
+            .collect::<String>(),
+    )
+    .map_err(|source| Error::WriteFailed {
+        path: record_path,
+        source,
+    })?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_every_density() {
+        let source = RgbaImage::from_pixel(64, 32, Rgba([255, 0, 0, 255]));
+        let files = render(&source, parse_color("#3ddc84").unwrap()).unwrap();
+        assert_eq!(files.len(), DENSITIES.len() * 3 + 1);
+        let legacy = image::load_from_memory(&files["mipmap-xxxhdpi/ic_launcher.webp"]).unwrap();
+        assert_eq!((legacy.width(), legacy.height()), (192, 192));
+        let foreground = image::load_from_memory(&files["mipmap-mdpi/ic_launcher_foreground.webp"])
+            .unwrap()
+            .into_rgba8();
+        assert_eq!(foreground.dimensions(), (108, 108));
+        // Scaled into the safe zone, so the corners stay transparent
+        assert_eq!(foreground.get_pixel(0, 0)[3], 0);
+        assert_eq!(foreground.get_pixel(54, 54), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn rejects_invalid_colors() {
+        assert!(parse_color("#fff").is_err());
+        assert!(parse_color("3ddc84").is_err());
+        assert!(parse_color("#3ddc8g").is_err());
+        assert!(parse_color("#€€").is_err());
+        assert!(parse_color("#+1+1+1").is_err());
+    }
+
+    #[test]
+    fn hashes_are_stable() {
+        assert_eq!(
+            hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
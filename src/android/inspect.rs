@@ -0,0 +1,392 @@
+//! Reporting what's inside a built APK or AAB.
+
+use super::{bundletool, env::Env, target::Target};
+use crate::{
+    target::TargetTrait as _,
+    util::cli::{Report, Reportable},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0:?} isn't an `.apk` or `.aab`")]
+    FormatUnknown(PathBuf),
+    #[error("Failed to open {path:?}: {source}")]
+    OpenFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to read {path:?}: {source}")]
+    ZipFailed {
+        path: PathBuf,
+        source: zip::result::ZipError,
+    },
+    #[error("Couldn't find `aapt2`; install the Android SDK Build-Tools")]
+    Aapt2Missing,
+    #[error(transparent)]
+    BundletoolInstallFailed(bundletool::InstallError),
+    #[error("Failed to dump the manifest of {path:?}: {source}")]
+    DumpFailed { path: PathBuf, source: io::Error },
+    #[error("{}", format_missing(.0))]
+    LibsMissing(Vec<(String, String)>),
+}
+
+fn format_missing(missing: &[(String, String)]) -> String {
+    missing
+        .iter()
+        .map(|(abi, lib)| format!("{} is missing {}", abi, lib))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::Aapt2Missing => Report::action_request("`aapt2` not found", self),
+            Self::BundletoolInstallFailed(err) => err.report(),
+            Self::LibsMissing(_) => Report::error("Some ABIs are missing native libraries", self),
+            _ => Report::error("Failed to inspect package", self),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Apk,
+    Aab,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "apk" => Some(Self::Apk),
+            "aab" => Some(Self::Aab),
+            _ => None,
+        }
+    }
+
+    /// The ABI and file name of a native library entry, if it is one.
+    fn lib_entry(self, name: &str) -> Option<(&str, &str)> {
+        let parts = name.split('/').collect::<Vec<_>>();
+        let (abi, file) = match (self, parts.as_slice()) {
+            (Self::Apk, ["lib", abi, file]) | (Self::Aab, [_, "lib", abi, file]) => (*abi, *file),
+            _ => return None,
+        };
+        file.ends_with(".so").then_some((abi, file))
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Manifest {
+    pub package: Option<String>,
+    pub version_name: Option<String>,
+    pub version_code: Option<String>,
+    pub min_sdk: Option<String>,
+    pub target_sdk: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+/// Finds `key=<quote>value<quote>` in a tag or line, making sure `key` isn't
+/// just the end of a longer attribute name.
+fn attr(text: &str, key: &str, quote: char) -> Option<String> {
+    let needle = format!("{}={}", key, quote);
+    text.match_indices(&needle)
+        .find(|(i, _)| {
+            text[..*i]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace)
+        })
+        .and_then(|(i, _)| {
+            let rest = &text[i + needle.len()..];
+            rest.find(quote).map(|end| rest[..end].to_owned())
+        })
+}
+
+/// Parses the output of `aapt2 dump badging`.
+fn parse_badging(output: &str) -> Manifest {
+    let mut manifest = Manifest::default();
+    for line in output.lines() {
+        if let Some(package) = line.strip_prefix("package:") {
+            manifest.package = attr(package, "name", '\'');
+            manifest.version_code = attr(package, "versionCode", '\'');
+            manifest.version_name = attr(package, "versionName", '\'');
+        } else if let Some(version) = line.strip_prefix("sdkVersion:") {
+            manifest.min_sdk = Some(version.trim_matches('\'').to_owned());
+        } else if let Some(version) = line.strip_prefix("targetSdkVersion:") {
+            manifest.target_sdk = Some(version.trim_matches('\'').to_owned());
+        } else if let Some(permission) = line.strip_prefix("uses-permission:") {
+            manifest.permissions.extend(attr(permission, "name", '\''));
+        }
+    }
+    manifest
+}
+
+/// Parses the XML manifest printed by `bundletool dump manifest`.
+fn parse_manifest_xml(xml: &str) -> Manifest {
+    let mut manifest = Manifest::default();
+    for tag in xml
+        .split('<')
+        .map(|tag| tag.split('>').next().unwrap_or(tag))
+    {
+        if tag.starts_with("manifest") {
+            manifest.package = attr(tag, "package", '"');
+            manifest.version_code = attr(tag, "android:versionCode", '"');
+            manifest.version_name = attr(tag, "android:versionName", '"');
+        } else if tag.starts_with("uses-sdk") {
+            manifest.min_sdk = attr(tag, "android:minSdkVersion", '"');
+            manifest.target_sdk = attr(tag, "android:targetSdkVersion", '"');
+        } else if tag.starts_with("uses-permission") {
+            manifest.permissions.extend(attr(tag, "android:name", '"'));
+        }
+    }
+    manifest
+}
+
+fn read_manifest(env: &Env, path: &Path, format: Format) -> Result<Manifest, Error> {
+    let dump_failed = |source| Error::DumpFailed {
+        path: path.to_owned(),
+        source,
+    };
+    match format {
+        Format::Apk => {
+            let aapt2 = env
+                .build_tools_path()
+                .map(|dir| dir.join(if cfg!(windows) { "aapt2.exe" } else { "aapt2" }))
+                .filter(|path| path.is_file())
+                .ok_or(Error::Aapt2Missing)?;
+            duct::cmd(
+                aapt2,
+                ["dump".as_ref(), "badging".as_ref(), path.as_os_str()],
+            )
+            .stderr_capture()
+            .read()
+            .map(|output| parse_badging(&output))
+            .map_err(dump_failed)
+        }
+        Format::Aab => {
+            bundletool::install(false).map_err(Error::BundletoolInstallFailed)?;
+            bundletool::dump_manifest(path)
+                .map(|xml| parse_manifest_xml(&xml))
+                .map_err(dump_failed)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lib {
+    pub name: String,
+    /// Uncompressed size in bytes.
+    pub size: u64,
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Native libraries in a package, keyed by ABI.
+pub fn read_libs(path: &Path, format: Format) -> Result<BTreeMap<String, Vec<Lib>>, Error> {
+    let file = fs::File::open(path).map_err(|source| Error::OpenFailed {
+        path: path.to_owned(),
+        source,
+    })?;
+    let zip_failed = |source| Error::ZipFailed {
+        path: path.to_owned(),
+        source,
+    };
+    let mut archive = zip::ZipArchive::new(file).map_err(zip_failed)?;
+    let mut libs = BTreeMap::<String, Vec<Lib>>::new();
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i).map_err(zip_failed)?;
+        if let Some((abi, name)) = format.lib_entry(entry.name()) {
+            libs.entry(abi.to_owned()).or_default().push(Lib {
+                name: name.to_owned(),
+                size: entry.size(),
+            });
+        }
+    }
+    for abi_libs in libs.values_mut() {
+        abi_libs.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    Ok(libs)
+}
+
+#[derive(Clone, Debug)]
+pub struct Contents {
+    pub path: PathBuf,
+    pub manifest: Manifest,
+    pub libs: BTreeMap<String, Vec<Lib>>,
+}
+
+impl Contents {
+    pub fn read(env: &Env, path: &Path) -> Result<Self, Error> {
+        let format =
+            Format::from_path(path).ok_or_else(|| Error::FormatUnknown(path.to_owned()))?;
+        let libs = read_libs(path, format)?;
+        let manifest = read_manifest(env, path, format)?;
+        Ok(Self {
+            path: path.to_owned(),
+            manifest,
+            libs,
+        })
+    }
+
+    /// Libraries that are present for some ABI but not for another, along
+    /// with any of `required` an included ABI lacks. ABIs that aren't in the
+    /// package at all aren't counted, since split packages only hold one.
+    pub fn missing_libs(&self, required: &[&str]) -> Vec<(String, String)> {
+        let expected = self
+            .libs
+            .values()
+            .flatten()
+            .map(|lib| lib.name.as_str())
+            .chain(required.iter().copied())
+            .collect::<BTreeSet<_>>();
+        self.libs
+            .iter()
+            .flat_map(|(abi, libs)| {
+                expected
+                    .iter()
+                    .filter(|name| !libs.iter().any(|lib| lib.name == **name))
+                    .map(|name| (abi.clone(), (*name).to_owned()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+impl Display for Contents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = "unknown".to_owned();
+        let Manifest {
+            package,
+            version_name,
+            version_code,
+            min_sdk,
+            target_sdk,
+            permissions,
+        } = &self.manifest;
+        writeln!(f, "{}", self.path.display())?;
+        writeln!(f, "  Package:     {}", package.as_ref().unwrap_or(&unknown))?;
+        writeln!(
+            f,
+            "  Version:     {} ({})",
+            version_name.as_ref().unwrap_or(&unknown),
+            version_code.as_ref().unwrap_or(&unknown)
+        )?;
+        writeln!(
+            f,
+            "  SDK:         min {}, target {}",
+            min_sdk.as_ref().unwrap_or(&unknown),
+            target_sdk.as_ref().unwrap_or(&unknown)
+        )?;
+        writeln!(f, "  Permissions:")?;
+        if permissions.is_empty() {
+            writeln!(f, "    (none)")?;
+        }
+        for permission in permissions {
+            writeln!(f, "    {}", permission)?;
+        }
+        write!(f, "  Native libraries:")?;
+        for target in Target::all().values() {
+            match self.libs.get(target.abi) {
+                Some(libs) => {
+                    write!(f, "\n    {}", target.abi)?;
+                    for lib in libs {
+                        write!(f, "\n      {:<32} {}", lib.name, format_size(lib.size))?;
+                    }
+                }
+                None => write!(f, "\n    {} (not included)", target.abi)?,
+            }
+        }
+        for (abi, libs) in &self.libs {
+            if Target::for_abi(abi).is_none() {
+                write!(f, "\n    {} (unsupported ABI)", abi)?;
+                for lib in libs {
+                    write!(f, "\n      {:<32} {}", lib.name, format_size(lib.size))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_badging() {
+        let output = "package: name='com.example.app' versionCode='1002003' versionName='1.2.3' platformBuildVersionName='13'\n\
+            sdkVersion:'24'\n\
+            targetSdkVersion:'33'\n\
+            uses-permission: name='android.permission.INTERNET'\n\
+            uses-permission: name='android.permission.VIBRATE' maxSdkVersion='30'\n\
+            application-label:'Example'\n";
+        assert_eq!(
+            parse_badging(output),
+            Manifest {
+                package: Some("com.example.app".to_owned()),
+                version_name: Some("1.2.3".to_owned()),
+                version_code: Some("1002003".to_owned()),
+                min_sdk: Some("24".to_owned()),
+                target_sdk: Some("33".to_owned()),
+                permissions: vec![
+                    "android.permission.INTERNET".to_owned(),
+                    "android.permission.VIBRATE".to_owned(),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn parses_manifest_xml() {
+        let xml = r#"<manifest xmlns:android="http://schemas.android.com/apk/res/android" android:compileSdkVersion="33" android:versionCode="7" android:versionName="0.1.0" package="com.example.app">
+  <uses-sdk android:minSdkVersion="24" android:targetSdkVersion="33"/>
+  <uses-permission android:name="android.permission.INTERNET"/>
+  <application android:hasCode="false"/>
+</manifest>"#;
+        let manifest = parse_manifest_xml(xml);
+        assert_eq!(manifest.package.as_deref(), Some("com.example.app"));
+        assert_eq!(manifest.version_code.as_deref(), Some("7"));
+        assert_eq!(manifest.min_sdk.as_deref(), Some("24"));
+        assert_eq!(manifest.permissions, vec!["android.permission.INTERNET"]);
+    }
+
+    #[test]
+    fn finds_missing_libs() {
+        let lib = |name: &str| Lib {
+            name: name.to_owned(),
+            size: 1,
+        };
+        let contents = Contents {
+            path: PathBuf::from("app.apk"),
+            manifest: Manifest::default(),
+            libs: BTreeMap::from([
+                (
+                    "arm64-v8a".to_owned(),
+                    vec![lib("libc++_shared.so"), lib("libexample.so")],
+                ),
+                ("x86_64".to_owned(), vec![lib("libc++_shared.so")]),
+            ]),
+        };
+        assert_eq!(
+            contents.missing_libs(&["libexample.so"]),
+            vec![("x86_64".to_owned(), "libexample.so".to_owned())]
+        );
+    }
+}
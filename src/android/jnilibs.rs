@@ -1,7 +1,6 @@
 use super::{config::Config, target::Target};
 use crate::{
     os,
-    target::TargetTrait as _,
     util::{
         cli::{Report, Reportable},
         ln, prefix_path,
@@ -81,8 +80,9 @@ impl JniLibs {
     }
 
     pub fn remove_broken_links(config: &Config) -> Result<(), RemoveBrokenLinksError> {
-        for abi_dir in Target::all()
-            .values()
+        for abi_dir in config
+            .targets()
+            .into_iter()
             .map(|target| path(config, *target))
             .filter(|path| path.is_dir())
         {
@@ -0,0 +1,372 @@
+//! Creating and inspecting signing keystores with the JDK's `keytool`.
+
+use crate::{
+    config::app::App,
+    util::cli::{Report, Reportable},
+};
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+pub static DEFAULT_PASSWORD_ENV: &str = "ANDROID_KEYSTORE_PASSWORD";
+// What Android Studio uses for the debug keystore it generates
+static DEBUG_PASSWORD: &str = "android";
+static DEBUG_DNAME: &str = "CN=Android Debug,O=Android,C=US";
+// Google Play requires upload keys to be valid until at least 2033
+const VALIDITY_DAYS: u32 = 10000;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Couldn't find `keytool`; install a JDK and set `JAVA_HOME` (tried {tried:?})")]
+    KeytoolMissing { tried: Vec<PathBuf> },
+    #[error("A keystore already exists at {0:?}")]
+    AlreadyExists(PathBuf),
+    #[error("No keystore exists at {0:?}")]
+    NotFound(PathBuf),
+    #[error("`keytool` failed: {0}")]
+    KeytoolFailed(std::io::Error),
+    #[error(
+        "Debug keystores use a well-known password, so they can't be used to sign release builds"
+    )]
+    DebugSigningUnsupported,
+    #[error("Failed to read {path:?}: {source}")]
+    ManifestReadFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse {path:?}: {source}")]
+    ManifestParseFailed {
+        path: PathBuf,
+        source: toml_edit::TomlError,
+    },
+    #[error("Failed to write {path:?}: {source}")]
+    ManifestWriteFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::KeytoolMissing { .. } => Report::action_request("JDK not found", self),
+            Self::AlreadyExists(_) => Report::action_request(
+                "Keystore already exists",
+                format!("{}; pass `--force` to replace it.", self),
+            ),
+            Self::NotFound(_) => Report::error("Keystore not found", self),
+            Self::KeytoolFailed(err) => Report::error("`keytool` failed", err),
+            Self::DebugSigningUnsupported
+            | Self::ManifestReadFailed { .. }
+            | Self::ManifestParseFailed { .. }
+            | Self::ManifestWriteFailed { .. } => {
+                Report::error("Failed to write signing metadata", self)
+            }
+        }
+    }
+}
+
+/// Finds `keytool` in `JAVA_HOME`, then in the JDK bundled with Android
+/// Studio, then on the `PATH`.
+pub fn keytool() -> Result<PathBuf, Error> {
+    let file_name = if cfg!(windows) {
+        "keytool.exe"
+    } else {
+        "keytool"
+    };
+    let mut java_homes = Vec::new();
+    if let Some(java_home) = std::env::var_os("JAVA_HOME") {
+        java_homes.push(PathBuf::from(java_home));
+    }
+    java_homes.extend(
+        [
+            "/Applications/Android Studio.app/Contents/jbr/Contents/Home",
+            "/opt/android-studio/jbr",
+            "C:\\Program Files\\Android\\Android Studio\\jbr",
+        ]
+        .map(PathBuf::from),
+    );
+    let mut tried = java_homes
+        .into_iter()
+        .map(|java_home| java_home.join("bin").join(file_name))
+        .collect::<Vec<_>>();
+    if let Some(path) = tried.iter().find(|path| path.is_file()) {
+        return Ok(path.clone());
+    }
+    which::which(file_name).map_err(|_| {
+        tried.push(PathBuf::from(file_name));
+        Error::KeytoolMissing { tried }
+    })
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    /// Signs builds uploaded to Google Play.
+    Upload,
+    /// Signs local debug builds, which lets a team share one debug signature.
+    Debug,
+}
+
+impl Kind {
+    pub fn name_list() -> &'static [&'static str] {
+        &["upload", "debug"]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "upload" => Some(Self::Upload),
+            "debug" => Some(Self::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn default_path(self) -> &'static str {
+        match self {
+            Self::Upload => "upload.jks",
+            Self::Debug => "debug.keystore",
+        }
+    }
+
+    pub fn default_alias(self) -> &'static str {
+        match self {
+            Self::Upload => "upload",
+            Self::Debug => "androiddebugkey",
+        }
+    }
+}
+
+/// How `keytool` gets the keystore password.
+#[derive(Clone, Debug)]
+pub enum Password {
+    /// The well-known debug keystore password.
+    Debug,
+    /// Read from an environment variable by `keytool` itself, so it never
+    /// shows up in the process list.
+    Env(String),
+    /// `keytool` asks for it.
+    Prompt,
+}
+
+impl Password {
+    fn args(&self, flags: &[&str]) -> Vec<String> {
+        flags
+            .iter()
+            .flat_map(|flag| match self {
+                Self::Debug => vec![flag.to_string(), DEBUG_PASSWORD.to_owned()],
+                Self::Env(var) => vec![format!("{}:env", flag), var.clone()],
+                Self::Prompt => vec![],
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct Keystore {
+    path: PathBuf,
+    password: Password,
+}
+
+impl Keystore {
+    pub fn new(path: impl Into<PathBuf>, password: Password) -> Self {
+        Self {
+            path: path.into(),
+            password,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Generates a keystore holding a single 2048-bit RSA key. With `force`,
+    /// an existing keystore is only replaced once the new one was generated.
+    pub fn create(
+        &self,
+        alias: &str,
+        dname: Option<&str>,
+        kind: Kind,
+        force: bool,
+    ) -> Result<(), Error> {
+        if self.path.exists() && !force {
+            return Err(Error::AlreadyExists(self.path.clone()));
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(Error::KeytoolFailed)?;
+        }
+        // keytool adds to existing keystores, so it gets a fresh file to
+        // write, which only replaces the old keystore if keytool succeeds
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(self.path.file_name().unwrap_or_default());
+        tmp_name.push(".tmp");
+        let tmp_path = self.path.with_file_name(tmp_name);
+        if tmp_path.exists() {
+            std::fs::remove_file(&tmp_path).map_err(Error::KeytoolFailed)?;
+        }
+        let dname = dname.or((kind == Kind::Debug).then_some(DEBUG_DNAME));
+        let mut args = vec![
+            "-genkeypair".to_owned(),
+            "-keystore".to_owned(),
+            tmp_path.to_string_lossy().into_owned(),
+            "-alias".to_owned(),
+            alias.to_owned(),
+            "-keyalg".to_owned(),
+            "RSA".to_owned(),
+            "-keysize".to_owned(),
+            "2048".to_owned(),
+            "-validity".to_owned(),
+            VALIDITY_DAYS.to_string(),
+        ];
+        if let Some(dname) = dname {
+            args.extend(["-dname".to_owned(), dname.to_owned()]);
+        }
+        args.extend(self.password.args(&["-storepass", "-keypass"]));
+        duct::cmd(keytool()?, args)
+            .run()
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path))
+            .map_err(|err| {
+                let _ = std::fs::remove_file(&tmp_path);
+                Error::KeytoolFailed(err)
+            })
+    }
+
+    fn list(&self) -> Result<duct::Expression, Error> {
+        if !self.path.is_file() {
+            return Err(Error::NotFound(self.path.clone()));
+        }
+        let mut args = vec![
+            "-list".to_owned(),
+            "-v".to_owned(),
+            "-keystore".to_owned(),
+            self.path.to_string_lossy().into_owned(),
+        ];
+        args.extend(self.password.args(&["-storepass"]));
+        Ok(duct::cmd(keytool()?, args))
+    }
+
+    /// Prints everything `keytool` knows about the keystore.
+    pub fn print_info(&self) -> Result<(), Error> {
+        self.list()?.run().map_err(Error::KeytoolFailed)?;
+        Ok(())
+    }
+
+    pub fn fingerprints(&self) -> Result<Vec<Fingerprints>, Error> {
+        // `keytool` prompts on stderr, so this still works with `Prompt`
+        let output = self.list()?.read().map_err(Error::KeytoolFailed)?;
+        Ok(parse_fingerprints(&output))
+    }
+}
+
+/// The certificate fingerprints of one key, as asked for by Google APIs and
+/// Play App Signing.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Fingerprints {
+    pub alias: String,
+    pub sha1: String,
+    pub sha256: String,
+}
+
+impl Display for Fingerprints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.alias)?;
+        writeln!(f, "  SHA-1:   {}", self.sha1)?;
+        write!(f, "  SHA-256: {}", self.sha256)
+    }
+}
+
+fn parse_fingerprints(output: &str) -> Vec<Fingerprints> {
+    let mut fingerprints = Vec::<Fingerprints>::new();
+    for line in output.lines().map(str::trim) {
+        if let Some(alias) = line.strip_prefix("Alias name:") {
+            fingerprints.push(Fingerprints {
+                alias: alias.trim().to_owned(),
+                ..Default::default()
+            });
+        } else if let Some(current) = fingerprints.last_mut() {
+            // Only the first certificate in a chain belongs to the key itself
+            if let Some(sha1) = line.strip_prefix("SHA1:") {
+                if current.sha1.is_empty() {
+                    current.sha1 = sha1.trim().to_owned();
+                }
+            } else if let Some(sha256) = line.strip_prefix("SHA256:") {
+                if current.sha256.is_empty() {
+                    current.sha256 = sha256.trim().to_owned();
+                }
+            }
+        }
+    }
+    fingerprints
+}
+
+/// Points `[package.metadata.cargo-android.signing]` at a keystore, keeping
+/// the rest of `Cargo.toml` as it was.
+pub fn write_signing_metadata(
+    app: &App,
+    keystore_path: &Path,
+    alias: &str,
+    password_env: &str,
+) -> Result<(), Error> {
+    let path = app.manifest_path();
+    let contents = std::fs::read_to_string(&path).map_err(|source| Error::ManifestReadFailed {
+        path: path.clone(),
+        source,
+    })?;
+    let mut manifest =
+        contents
+            .parse::<toml_edit::Document>()
+            .map_err(|source| Error::ManifestParseFailed {
+                path: path.clone(),
+                source,
+            })?;
+    let keystore_path = keystore_path
+        .strip_prefix(app.root_dir())
+        .unwrap_or(keystore_path);
+    let mut signing = toml_edit::Table::new();
+    signing["keystore-path"] = toml_edit::value(keystore_path.to_string_lossy().replace('\\', "/"));
+    signing["key-alias"] = toml_edit::value(alias);
+    signing["store-password-env"] = toml_edit::value(password_env);
+    let android = &mut manifest["package"]["metadata"]["cargo-android"];
+    if android.is_none() {
+        let mut table = toml_edit::Table::new();
+        // Only the `signing` table needs a header
+        table.set_implicit(true);
+        *android = toml_edit::Item::Table(table);
+    }
+    if let Some(android) = android.as_table_like_mut() {
+        android.insert("signing", toml_edit::Item::Table(signing));
+    }
+    std::fs::write(&path, manifest.to_string())
+        .map_err(|source| Error::ManifestWriteFailed { path, source })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_fingerprints() {
+        let output = "Keystore type: PKCS12\n\
+            Keystore provider: SUN\n\
+            \n\
+            Your keystore contains 1 entry\n\
+            \n\
+            Alias name: upload\n\
+            Entry type: PrivateKeyEntry\n\
+            Certificate chain length: 1\n\
+            Certificate[1]:\n\
+            Owner: CN=Test\n\
+            Certificate fingerprints:\n\
+            \t SHA1: E4:E0:55:97:9D:97:F6:31:5D:5D:BE:62:9A:F3:73:39:57:2B:42:21\n\
+            \t SHA256: 5D:C3:A3:0B:81:DF:E6:EB:D2:F9:1B:36:8C:53:B5:FA:EA:93:5C:9D:DE:5F:4B:40:9D:C9:ED:D2:87:44:0A:50\n\
+            Signature algorithm name: SHA256withRSA\n";
+        assert_eq!(
+            parse_fingerprints(output),
+            vec![Fingerprints {
+                alias: "upload".to_owned(),
+                sha1: "E4:E0:55:97:9D:97:F6:31:5D:5D:BE:62:9A:F3:73:39:57:2B:42:21".to_owned(),
+                sha256: "5D:C3:A3:0B:81:DF:E6:EB:D2:F9:1B:36:8C:53:B5:FA:EA:93:5C:9D:DE:5F:4B:40:9D:C9:ED:D2:87:44:0A:50".to_owned(),
+            }]
+        );
+    }
+}
@@ -6,13 +6,21 @@ mod bundletool;
 pub mod cli;
 pub mod config;
 pub mod device;
+pub mod device_test;
 pub mod emulator;
 pub mod env;
+pub mod icon;
+pub mod inspect;
 mod jnilibs;
+pub mod keystore;
 pub mod ndk;
 pub(crate) mod project;
+pub mod size;
 mod source_props;
+pub mod symbolicator;
+pub mod symbols;
 pub mod target;
+pub mod version;
 
 pub static NAME: &str = "android";
 pub static DEFAULT_ACTIVITY: &str = "android.app.NativeActivity";
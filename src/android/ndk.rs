@@ -6,7 +6,7 @@ use crate::{
     os::consts,
     util::{
         cli::{Report, Reportable},
-        VersionDouble,
+        VersionDouble, VersionTriple,
     },
 };
 use once_cell_regex::regex_multi_line;
@@ -14,10 +14,11 @@ use std::{
     collections::HashSet,
     fmt::{self, Display},
     path::{Path, PathBuf},
+    str::FromStr,
 };
 use thiserror::Error;
 
-const MIN_NDK_VERSION: NdkVersion = NdkVersion(VersionDouble::new(19, 0));
+const MIN_NDK_VERSION: NdkVersion = NdkVersion::new(19, 0);
 
 #[cfg(target_os = "macos")]
 pub fn host_tag() -> &'static str {
@@ -102,6 +103,12 @@ impl MissingToolError {
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct NdkVersion(VersionDouble);
 
+impl NdkVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self(VersionDouble::new(major, minor))
+    }
+}
+
 impl Display for NdkVersion {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "r{}", self.0.major)?;
@@ -128,13 +135,134 @@ impl From<source_props::Revision> for NdkVersion {
     }
 }
 
+/// An `ndk-version` pin. An exact version only has to match as many
+/// components as it gives, so `26` matches any r26 and `26.1` any r26b.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VersionReq {
+    Exact(Vec<u32>),
+    AtLeast(Vec<u32>),
+}
+
+#[derive(Debug, Error)]
+#[error("{0:?} isn't a valid NDK version; expected something like \"26.1.10909125\" or \">=25\"")]
+pub struct VersionReqInvalid(String);
+
+impl FromStr for VersionReq {
+    type Err = VersionReqInvalid;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || VersionReqInvalid(s.to_owned());
+        let (at_least, version) = match s.trim().strip_prefix(">=") {
+            Some(version) => (true, version.trim()),
+            None => (false, s.trim()),
+        };
+        let parts = version
+            .split('.')
+            .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        if parts.len() > 3 {
+            return Err(invalid());
+        }
+        Ok(if at_least {
+            Self::AtLeast(parts)
+        } else {
+            Self::Exact(parts)
+        })
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (prefix, parts) = match self {
+            Self::Exact(parts) => ("", parts),
+            Self::AtLeast(parts) => (">=", parts),
+        };
+        let parts = parts.iter().map(ToString::to_string).collect::<Vec<_>>();
+        write!(f, "{}{}", prefix, parts.join("."))
+    }
+}
+
+impl VersionReq {
+    pub fn matches(&self, version: &VersionTriple) -> bool {
+        let version = [version.major, version.minor, version.patch];
+        match self {
+            Self::Exact(parts) => parts.iter().zip(version).all(|(part, v)| *part == v),
+            Self::AtLeast(parts) => version[..parts.len()] >= parts[..],
+        }
+    }
+}
+
+/// An NDK found while looking for one to use.
+#[derive(Debug)]
+pub struct Candidate {
+    pub path: PathBuf,
+    pub version: Result<source_props::Revision, source_props::Error>,
+}
+
+impl Candidate {
+    fn new(path: PathBuf) -> Self {
+        let version =
+            SourceProps::from_path(path.join("source.properties")).map(|props| props.pkg.revision);
+        Self { path, version }
+    }
+}
+
+impl Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Ok(version) => write!(f, "{:?} (v{})", self.path, version),
+            Err(err) => write!(f, "{:?} (unknown version: {})", self.path, err),
+        }
+    }
+}
+
+/// The newest candidate that satisfies `version_req`.
+fn choose<'a>(
+    candidates: &'a [Candidate],
+    version_req: Option<&VersionReq>,
+) -> Option<&'a Candidate> {
+    candidates
+        .iter()
+        .filter_map(|candidate| {
+            let version = candidate.version.as_ref().ok()?;
+            version_req
+                .is_none_or(|req| req.matches(&version.triple))
+                .then_some((candidate, version.triple))
+        })
+        .max_by_key(|(_, version)| *version)
+        .map(|(candidate, _)| candidate)
+}
+
+fn matching(version_req: Option<&VersionReq>) -> String {
+    version_req
+        .map(|req| format!(" matching `ndk-version = \"{}\"`", req))
+        .unwrap_or_default()
+}
+
+fn found(candidates: &[Candidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| format!("\n  Found {}", candidate))
+        .collect()
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     // TODO: link to docs/etc.
-    #[error("Have you installed the NDK? The `NDK_HOME` environment variable isn't set, and is required: {0}")]
-    NdkHomeNotSet(#[from] std::env::VarError),
     #[error("Have you installed the NDK? The `NDK_HOME` environment variable is set, but doesn't point to an existing directory.")]
     NdkHomeNotADir,
+    #[error(
+        "Have you installed the NDK? `NDK_HOME` isn't set, and no NDK{} was found in {:?} or {:?}.{}",
+        matching(.version_req.as_ref()),
+        .android_home.join("ndk"),
+        .android_home.join("ndk-bundle"),
+        found(.candidates)
+    )]
+    NotFound {
+        android_home: PathBuf,
+        version_req: Option<VersionReq>,
+        candidates: Vec<Candidate>,
+    },
     #[error("Failed to lookup version of installed NDK: {0}")]
     VersionLookupFailed(#[from] source_props::Error),
     #[error("At least NDK {you_need} is required (you currently have NDK {you_have})")]
@@ -172,17 +300,29 @@ pub struct Env {
 }
 
 impl Env {
-    pub fn new() -> Result<Self, Error> {
-        let ndk_home = std::env::var("NDK_HOME")
-            .map_err(Error::NdkHomeNotSet)
-            .map(PathBuf::from)
-            .and_then(|ndk_home| {
-                if ndk_home.is_dir() {
-                    Ok(ndk_home)
-                } else {
-                    Err(Error::NdkHomeNotADir)
+    /// Uses `NDK_HOME` if it's set, and otherwise the newest NDK installed in
+    /// the SDK that satisfies `version_req`.
+    pub fn new(android_home: &Path, version_req: Option<&VersionReq>) -> Result<Self, Error> {
+        let ndk_home = match std::env::var_os("NDK_HOME") {
+            Some(ndk_home) => {
+                let ndk_home = PathBuf::from(ndk_home);
+                if !ndk_home.is_dir() {
+                    return Err(Error::NdkHomeNotADir);
                 }
-            })?;
+                if let Some(req) = version_req {
+                    let candidate = Candidate::new(ndk_home.clone());
+                    if choose(std::slice::from_ref(&candidate), Some(req)).is_none() {
+                        log::warn!(
+                            "using {} from `NDK_HOME`, even though it doesn't match `ndk-version = \"{}\"`",
+                            candidate,
+                            req
+                        );
+                    }
+                }
+                ndk_home
+            }
+            None => Self::detect(android_home, version_req)?,
+        };
         let env = Self { ndk_home };
         let version = env
             .version()
@@ -198,6 +338,44 @@ impl Env {
         }
     }
 
+    fn detect(android_home: &Path, version_req: Option<&VersionReq>) -> Result<PathBuf, Error> {
+        // Side-by-side NDKs, which is how Android Studio installs them
+        let mut candidates = std::fs::read_dir(android_home.join("ndk"))
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.is_dir())
+                    .map(Candidate::new)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        candidates.sort_by(|a, b| a.path.cmp(&b.path));
+        let chosen = match choose(&candidates, version_req) {
+            Some(candidate) => Some(candidate),
+            None => {
+                // The old single-NDK location
+                let bundle = android_home.join("ndk-bundle");
+                if bundle.is_dir() {
+                    candidates.push(Candidate::new(bundle));
+                    choose(&candidates[candidates.len() - 1..], version_req)
+                } else {
+                    None
+                }
+            }
+        };
+        match chosen {
+            Some(candidate) => {
+                log::info!("`NDK_HOME` isn't set; using {}", candidate);
+                Ok(candidate.path.clone())
+            }
+            None => Err(Error::NotFound {
+                android_home: android_home.to_owned(),
+                version_req: version_req.cloned(),
+                candidates,
+            }),
+        }
+    }
+
     pub fn home(&self) -> &Path {
         &self.ndk_home
     }
@@ -275,6 +453,21 @@ impl Env {
         MissingToolError::check_file(self.tool_dir()?.join(bin_path), "ar")
     }
 
+    pub fn symbolizer_path(&self) -> Result<PathBuf, MissingToolError> {
+        MissingToolError::check_file(
+            self.tool_dir()?.join(consts::LLVM_SYMBOLIZER),
+            "llvm-symbolizer",
+        )
+    }
+
+    pub fn strip_path(&self) -> Result<PathBuf, MissingToolError> {
+        MissingToolError::check_file(self.tool_dir()?.join(consts::LLVM_STRIP), "llvm-strip")
+    }
+
+    pub fn nm_path(&self) -> Result<PathBuf, MissingToolError> {
+        MissingToolError::check_file(self.tool_dir()?.join(consts::LLVM_NM), "llvm-nm")
+    }
+
     fn readelf_path(&self, triple: &str) -> Result<PathBuf, MissingToolError> {
         let ndk_ver = self.version().unwrap_or_default();
         let bin_path = if ndk_ver.triple.major >= 23 {
@@ -313,3 +506,66 @@ impl Env {
             .collect())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        req,
+        version,
+        matches,
+        case("26.1.10909125", (26, 1, 10909125), true),
+        case("26", (26, 1, 10909125), true),
+        case("26.1", (26, 0, 10792818), false),
+        case(">=25", (26, 1, 10909125), true),
+        case(">= 26.1", (26, 0, 10792818), false),
+        case(">=26.1", (27, 0, 11718014), true)
+    )]
+    fn matches_version_req(req: &str, version: (u32, u32, u32), matches: bool) {
+        let req = req.parse::<VersionReq>().unwrap();
+        let (major, minor, patch) = version;
+        assert_eq!(
+            req.matches(&VersionTriple {
+                major,
+                minor,
+                patch
+            }),
+            matches
+        );
+    }
+
+    #[rstest(req, case(""), case(">="), case("r26"), case("26.1.2.3"))]
+    fn rejects_invalid_version_req(req: &str) {
+        assert!(req.parse::<VersionReq>().is_err());
+    }
+
+    #[test]
+    fn chooses_newest_matching_ndk() {
+        let candidate = |name: &str, major, minor| Candidate {
+            path: PathBuf::from(name),
+            version: Ok(source_props::Revision {
+                triple: VersionTriple {
+                    major,
+                    minor,
+                    patch: 0,
+                },
+                beta: None,
+            }),
+        };
+        let candidates = [
+            candidate("25.2", 25, 2),
+            candidate("27.0", 27, 0),
+            candidate("26.1", 26, 1),
+        ];
+        let chosen = |req: Option<&str>| {
+            choose(&candidates, req.map(|req| req.parse().unwrap()).as_ref())
+                .map(|candidate| candidate.path.to_str().unwrap())
+        };
+        assert_eq!(chosen(None), Some("27.0"));
+        assert_eq!(chosen(Some("26")), Some("26.1"));
+        assert_eq!(chosen(Some(">=25.3")), Some("27.0"));
+        assert_eq!(chosen(Some("24")), None);
+    }
+}
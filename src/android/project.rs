@@ -1,14 +1,14 @@
 use super::{
     config::{Config, Metadata},
     env::Env,
-    ndk,
+    icon, ndk,
     target::Target,
+    version,
 };
 use crate::{
     android::{config::DEFAULT_VULKAN_VALIDATION, DEFAULT_ACTIVITY, DEFAULT_THEME_PARENT},
     bicycle, dot_cargo,
     os::{self, replace_path_separator},
-    target::TargetTrait as _,
     templating::{self, Pack},
     util::{
         self,
@@ -24,6 +24,8 @@ use std::{
 
 pub static TEMPLATE_PACK: &str = "android-studio";
 pub static ASSET_PACK_TEMPLATE_PACK: &str = "android-studio-asset-pack";
+/// Launcher icons are generated from this image in the asset dir, if it exists.
+pub static ICON_SOURCE: &str = "branding/icon.png";
 
 #[derive(Debug)]
 pub enum Error {
@@ -50,6 +52,8 @@ pub enum Error {
         cause: std::io::Error,
     },
     AssetSourceInvalid(PathBuf),
+    VersionFailed(version::Error),
+    IconFailed(icon::Error),
 }
 
 impl Reportable for Error {
@@ -85,6 +89,8 @@ impl Reportable for Error {
                 format!("Asset source at {:?} invalid", src),
                 "Asset sources must be either a directory or a file",
             ),
+            Self::VersionFailed(err) => err.report(),
+            Self::IconFailed(err) => err.report(),
         }
     }
 }
@@ -102,7 +108,7 @@ pub fn gen(
 ) -> Result<(), Error> {
     if !skip_targets_install {
         println!("Installing Android toolchains...");
-        Target::install_all().map_err(Error::RustupFailed)?;
+        Target::install_enabled(config).map_err(Error::RustupFailed)?;
     }
     println!("Generating Android Studio project...");
     let src = Pack::lookup_platform(TEMPLATE_PACK)
@@ -110,6 +116,18 @@ pub fn gen(
         .expect_local();
     let dest = config.project_dir();
 
+    let crate_version =
+        version::crate_version(&config.app().manifest_path()).map_err(Error::VersionFailed)?;
+    let version_name = metadata
+        .version_name()
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| crate_version.clone());
+    let version_code = match metadata.version_code() {
+        Some(version_code) => version_code,
+        None => version::version_code(metadata.version_code_formula(), &crate_version)
+            .map_err(Error::VersionFailed)?,
+    };
+
     let asset_packs = metadata.asset_packs().unwrap_or_default();
     bike.filter_and_process(
         src,
@@ -123,20 +141,15 @@ pub fn gen(
                 )),
             );
             map.insert("root-dir", config.app().root_dir());
+            let targets = config.targets();
             map.insert(
                 "abi-list",
-                Target::all()
-                    .values()
-                    .map(|target| target.abi)
-                    .collect::<Vec<_>>(),
+                targets.iter().map(|target| target.abi).collect::<Vec<_>>(),
             );
-            map.insert("target-list", Target::all().keys().collect::<Vec<_>>());
+            map.insert("target-list", config.target_names());
             map.insert(
                 "arch-list",
-                Target::all()
-                    .values()
-                    .map(|target| target.arch)
-                    .collect::<Vec<_>>(),
+                targets.iter().map(|target| target.arch).collect::<Vec<_>>(),
             );
             map.insert("android-app-plugins", metadata.app_plugins());
             map.insert(
@@ -167,6 +180,22 @@ pub fn gen(
                     .vulkan_validation()
                     .unwrap_or(DEFAULT_VULKAN_VALIDATION),
             );
+            map.insert(
+                "android-signing",
+                metadata.signing().map(|signing| {
+                    serde_json::json!({
+                        "keystore-path": replace_path_separator(
+                            signing.keystore_path(config.app()).into_os_string()
+                        )
+                        .to_string_lossy(),
+                        "key-alias": signing.key_alias(),
+                        "store-password-env": signing.store_password_env(),
+                        "key-password-env": signing.key_password_env(),
+                    })
+                }),
+            );
+            map.insert("version-name", &version_name);
+            map.insert("version-code", version_code);
             map.insert("android-app-permissions", metadata.app_permissions());
             map.insert(
                 "android-app-theme-parent",
@@ -229,8 +258,34 @@ pub fn gen(
     os::ln::force_symlink_relative(config.app().asset_dir(), dest, ln::TargetStyle::Directory)
         .map_err(Error::AssetDirSymlinkFailed)?;
 
+    let icon_path = config.app().asset_dir().join(ICON_SOURCE);
+    if icon_path.is_file() {
+        let background =
+            icon::parse_color(icon::DEFAULT_BACKGROUND).expect("developer error: invalid color");
+        let summary =
+            icon::generate(config, &icon_path, background, false).map_err(Error::IconFailed)?;
+        if !summary.skipped.is_empty() {
+            Report::action_request(
+                "Some launcher icons were customized, so they weren't regenerated",
+                format!(
+                    "Run `cargo mobile icon {} --force` to replace them.",
+                    icon_path.display()
+                ),
+            )
+            .print(wrapper);
+        }
+    }
+
     {
-        for target in Target::all().values() {
+        for target in config.targets() {
+            if let Err(err) = target.check_ndk(&env.ndk) {
+                log::warn!(
+                    "not configuring `{}` in `.cargo/config.toml`: {}",
+                    target.triple,
+                    err
+                );
+                continue;
+            }
             dot_cargo.insert_target(
                 target.triple.to_owned(),
                 target
@@ -0,0 +1,369 @@
+//! Measuring the Rust library, and keeping it within a size budget.
+
+use super::{
+    config::{Config, SizeBudget},
+    inspect::format_size,
+    ndk, symbols,
+    target::Target,
+};
+use crate::{
+    opts::Profile,
+    util::cli::{Report, Reportable},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    MissingTool(ndk::MissingToolError),
+    #[error("No library at {0:?}; build it first")]
+    LibMissing(PathBuf),
+    #[error("Failed to read {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to read symbols from {path:?}: {source}")]
+    NmFailed { path: PathBuf, source: io::Error },
+    #[error("Baseline at {path:?} is invalid: {source}")]
+    BaselineInvalid {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Failed to save baseline to {path:?}: {source}")]
+    BaselineSaveFailed { path: PathBuf, source: io::Error },
+    #[error("{}", .0.join("\n"))]
+    OverBudget(Vec<String>),
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::MissingTool(err) => Report::error("Failed to locate `llvm-nm`", err),
+            Self::OverBudget(_) => Report::action_request(
+                "Native library size budget exceeded",
+                format!(
+                    "{}\n\nIf the growth is expected, pass `--save-baseline` or raise the budget in `[package.metadata.cargo-android.size-budget]`.",
+                    self
+                ),
+            ),
+            _ => Report::error("Failed to measure library size", self),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Parses `llvm-nm --print-size --radix=d` output, keeping only symbols that
+/// take up space in the file.
+fn parse_nm(output: &str) -> Vec<Symbol> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(4, ' ');
+            let (_address, size, ty, name) =
+                (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+            let size = size.parse::<u64>().ok()?;
+            // bss doesn't take up any space, and undefined symbols aren't ours
+            (size > 0 && "tTrRdD".contains(ty)).then(|| Symbol {
+                name: name.to_owned(),
+                size,
+            })
+        })
+        .collect()
+}
+
+/// The crate a symbol most likely came from. Generic code is attributed to
+/// whichever crate appears first, which is usually the one it's defined in.
+fn crate_name(symbol: &str) -> String {
+    match rustc_demangle::try_demangle(symbol) {
+        Ok(demangled) => {
+            let demangled = format!("{:#}", demangled);
+            demangled
+                .trim_start_matches(['<', '&', '*', ' '])
+                .trim_start_matches("mut ")
+                .trim_start_matches("dyn ")
+                .split("::")
+                .next()
+                .filter(|name| !name.is_empty())
+                .unwrap_or("[unknown]")
+                .to_owned()
+        }
+        Err(_) => "[C/C++]".to_owned(),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LibSize {
+    pub abi: String,
+    /// What gets packaged, i.e. the stripped copy for release builds.
+    pub size: u64,
+    pub symbols: Vec<Symbol>,
+    pub crates: Vec<(String, u64)>,
+}
+
+impl LibSize {
+    pub fn measure(
+        config: &Config,
+        ndk: &ndk::Env,
+        target: &Target,
+        profile: Profile,
+    ) -> Result<Self, Error> {
+        let lib_path = config
+            .app()
+            .target_dir(target.triple, profile)
+            .join(config.so_name());
+        if !lib_path.is_file() {
+            return Err(Error::LibMissing(lib_path));
+        }
+        let stripped_path = symbols::stripped_path(&lib_path);
+        let packaged_path = if profile.release() && stripped_path.is_file() {
+            &stripped_path
+        } else {
+            &lib_path
+        };
+        let size = fs::metadata(packaged_path)
+            .map_err(|source| Error::ReadFailed {
+                path: packaged_path.clone(),
+                source,
+            })?
+            .len();
+        // Symbols have to come from the unstripped library
+        let output = duct::cmd(
+            ndk.nm_path().map_err(Error::MissingTool)?,
+            [
+                "--print-size".as_ref(),
+                "--radix=d".as_ref(),
+                lib_path.as_os_str(),
+            ],
+        )
+        .stderr_capture()
+        .read()
+        .map_err(|source| Error::NmFailed {
+            path: lib_path.clone(),
+            source,
+        })?;
+        let mut crates = HashMap::<String, u64>::new();
+        let mut symbols = parse_nm(&output)
+            .into_iter()
+            .map(|symbol| {
+                *crates.entry(crate_name(&symbol.name)).or_default() += symbol.size;
+                Symbol {
+                    name: format!("{:#}", rustc_demangle::demangle(&symbol.name)),
+                    ..symbol
+                }
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        let mut crates = crates.into_iter().collect::<Vec<_>>();
+        crates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(Self {
+            abi: target.abi.to_owned(),
+            size,
+            symbols,
+            crates,
+        })
+    }
+
+    pub fn display(&self, top: usize, baseline: Option<u64>) -> LibSizeDisplay<'_> {
+        LibSizeDisplay {
+            lib: self,
+            top,
+            baseline,
+        }
+    }
+}
+
+pub struct LibSizeDisplay<'a> {
+    lib: &'a LibSize,
+    top: usize,
+    baseline: Option<u64>,
+}
+
+impl Display for LibSizeDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { lib, top, baseline } = self;
+        write!(f, "{}: {}", lib.abi, format_size(lib.size))?;
+        if let Some(baseline) = baseline {
+            write!(
+                f,
+                " ({:+.1}% from baseline {})",
+                growth_percent(*baseline, lib.size),
+                format_size(*baseline)
+            )?;
+        }
+        write!(f, "\n  Largest crates:")?;
+        for (name, size) in lib.crates.iter().take(*top) {
+            write!(f, "\n    {:>10}  {}", format_size(*size), name)?;
+        }
+        write!(f, "\n  Largest symbols:")?;
+        for symbol in lib.symbols.iter().take(*top) {
+            write!(f, "\n    {:>10}  {}", format_size(symbol.size), symbol.name)?;
+        }
+        Ok(())
+    }
+}
+
+fn growth_percent(baseline: u64, size: u64) -> f64 {
+    if baseline == 0 {
+        0.0
+    } else {
+        (size as f64 - baseline as f64) / baseline as f64 * 100.0
+    }
+}
+
+/// Library sizes to compare later builds against, keyed by ABI.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Baseline {
+    pub profile: String,
+    pub sizes: BTreeMap<String, u64>,
+}
+
+impl Baseline {
+    pub fn new(profile: Profile, libs: &[LibSize]) -> Self {
+        Self {
+            profile: profile.as_str().to_owned(),
+            sizes: libs.iter().map(|lib| (lib.abi.clone(), lib.size)).collect(),
+        }
+    }
+
+    /// Only a baseline for the same profile is returned, since comparing
+    /// debug and release sizes is meaningless.
+    pub fn load(path: &Path, profile: Profile) -> Result<Option<Self>, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(Error::ReadFailed {
+                    path: path.to_owned(),
+                    source,
+                })
+            }
+        };
+        let baseline =
+            serde_json::from_str::<Self>(&contents).map_err(|source| Error::BaselineInvalid {
+                path: path.to_owned(),
+                source,
+            })?;
+        Ok((baseline.profile == profile.as_str()).then_some(baseline))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let contents = serde_json::to_string_pretty(self)
+            .expect("developer error: baseline wasn't serializable");
+        fs::write(path, contents + "\n").map_err(|source| Error::BaselineSaveFailed {
+            path: path.to_owned(),
+            source,
+        })
+    }
+}
+
+/// Every way `libs` exceed `budget`, as human-readable lines.
+pub fn check_budget(
+    budget: &SizeBudget,
+    libs: &[LibSize],
+    baseline: Option<&Baseline>,
+) -> Vec<String> {
+    let mut violations = Vec::new();
+    for lib in libs {
+        if let Some(max_size) = budget.max_size.filter(|max| lib.size > *max) {
+            violations.push(format!(
+                "{} is {}, over the maximum of {}",
+                lib.abi,
+                format_size(lib.size),
+                format_size(max_size)
+            ));
+        }
+        let old_size = baseline.and_then(|baseline| baseline.sizes.get(&lib.abi));
+        if let (Some(max_growth), Some(old_size)) = (budget.max_growth_percent, old_size) {
+            let growth = growth_percent(*old_size, lib.size);
+            if growth > max_growth {
+                violations.push(format!(
+                    "{} grew {:.1}% from {} to {}, over the maximum of {}%",
+                    lib.abi,
+                    growth,
+                    format_size(*old_size),
+                    format_size(lib.size),
+                    max_growth
+                ));
+            }
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn parses_nm_output() {
+        let output = "0000000000012340 0000000000000128 T _ZN4core3fmt5write17h0123456789abcdefE\n\
+            0000000000020000 0000000000000016 B _ZN7example5STATE17h0123456789abcdefE\n\
+            0000000000000000 0000000000000000 U malloc\n\
+            0000000000030000 0000000000000064 r .Lanon.1\n";
+        assert_eq!(
+            parse_nm(output),
+            vec![
+                Symbol {
+                    name: "_ZN4core3fmt5write17h0123456789abcdefE".to_owned(),
+                    size: 128,
+                },
+                Symbol {
+                    name: ".Lanon.1".to_owned(),
+                    size: 64,
+                },
+            ]
+        );
+    }
+
+    #[rstest(
+        symbol,
+        name,
+        case("_ZN4core3fmt5write17h0123456789abcdefE", "core"),
+        case(
+            "_ZN60_$LT$alloc..string..String$u20$as$u20$core..fmt..Display$GT$3fmt17h0123456789abcdefE",
+            "alloc"
+        ),
+        case("ANativeActivity_onCreate", "[C/C++]")
+    )]
+    fn attributes_symbols_to_crates(symbol: &str, name: &str) {
+        assert_eq!(crate_name(symbol), name);
+    }
+
+    #[test]
+    fn checks_budget() {
+        let lib = |abi: &str, size| LibSize {
+            abi: abi.to_owned(),
+            size,
+            symbols: Vec::new(),
+            crates: Vec::new(),
+        };
+        let budget = SizeBudget {
+            max_size: Some(1000),
+            max_growth_percent: Some(5.0),
+            baseline_path: None,
+        };
+        let baseline = Baseline {
+            profile: "release".to_owned(),
+            sizes: BTreeMap::from([("arm64-v8a".to_owned(), 900), ("x86_64".to_owned(), 900)]),
+        };
+        let violations = check_budget(
+            &budget,
+            &[lib("arm64-v8a", 920), lib("x86_64", 1001)],
+            Some(&baseline),
+        );
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].starts_with("x86_64 is"));
+        assert!(violations[1].starts_with("x86_64 grew"));
+    }
+}
@@ -0,0 +1,552 @@
+use super::{
+    adb::logcat_parser::{self, Entry},
+    config::Config,
+    jnilibs, ndk, symbols,
+    target::Target,
+};
+use crate::{
+    opts::Profile,
+    util::{
+        cli::{Report, Reportable},
+        last_modified,
+    },
+};
+use once_cell_regex::regex;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs::File,
+    io::{self, Read as _, Seek as _, SeekFrom},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    SymbolizerMissing(#[from] ndk::MissingToolError),
+    #[error("Failed to run `llvm-symbolizer` on {lib:?}: {source}")]
+    SymbolizerFailed { lib: PathBuf, source: io::Error },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::SymbolizerMissing(err) => {
+                Report::error("`llvm-symbolizer` wasn't found in the NDK", err)
+            }
+            Self::SymbolizerFailed { lib, source } => {
+                Report::error(format!("Failed to symbolicate frames in {:?}", lib), source)
+            }
+        }
+    }
+}
+
+/// Demangles Rust symbols, leaving anything else untouched.
+pub fn demangle(symbol: &str) -> String {
+    match rustc_demangle::try_demangle(symbol) {
+        Ok(demangled) => format!("{:#}", demangled),
+        // `llvm-symbolizer` demangles legacy Rust symbols as if they were C++,
+        // which leaves the hash on the end.
+        Err(_) => regex!(r"::h[0-9a-f]{16}$").replace(symbol, "").into_owned(),
+    }
+}
+
+/// A frame from a tombstone backtrace, i.e.
+/// `#00 pc 000000000004f0e8  /data/app/.../lib/arm64/libfoo.so (foo+24) (BuildId: 1f2e...)`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    pub index: u32,
+    pub pc: u64,
+    pub path: String,
+    pub symbol: Option<String>,
+    pub build_id: Option<String>,
+}
+
+impl Frame {
+    fn from_line(line: &str) -> Option<Self> {
+        let caps =
+            regex!(r"^\s*#(?P<index>\d+)\s+pc\s+(?P<pc>[0-9a-fA-F]+)\s+(?P<path>\S+)(?P<rest>.*)$")
+                .captures(line)?;
+        // What follows the path is any of `(offset 0x1000)`, `(symbol+24)` and
+        // `(BuildId: 1f2e...)`, in that order. Symbols can contain parentheses
+        // themselves, so peel off the other two first.
+        let mut rest = caps["rest"].trim();
+        let build_id = regex!(r"\(BuildId: ([0-9a-fA-F]+)\)$")
+            .captures(rest)
+            .map(|build_id| {
+                rest = rest[..build_id.get(0).unwrap().start()].trim();
+                build_id[1].to_lowercase()
+            });
+        if let Some(offset) = regex!(r"^\(offset 0x[0-9a-fA-F]+\)").find(rest) {
+            rest = rest[offset.end()..].trim();
+        }
+        let symbol = rest
+            .strip_prefix('(')
+            .and_then(|rest| rest.strip_suffix(')'))
+            .map(|symbol| regex!(r"\+\d+$").replace(symbol, "").into_owned());
+        Some(Self {
+            index: caps["index"].parse().ok()?,
+            pc: u64::from_str_radix(&caps["pc"], 16).ok()?,
+            path: caps["path"].to_owned(),
+            symbol,
+            build_id,
+        })
+    }
+
+    /// The file name of the library, which for libraries loaded straight from
+    /// an APK is the part after the `!`.
+    pub fn library(&self) -> &str {
+        self.path
+            .rsplit(['/', '!'])
+            .next()
+            .unwrap_or(self.path.as_str())
+    }
+}
+
+/// A native crash, as reported by `debuggerd` in logcat or in a tombstone.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Crash {
+    /// The process, signal and abort message lines.
+    pub header: Vec<String>,
+    /// Frames of the crashing thread.
+    pub frames: Vec<Frame>,
+}
+
+/// Finds every native crash in logcat output or a tombstone.
+pub fn parse(text: &str) -> Vec<Crash> {
+    let mut crashes = Vec::new();
+    let mut current = Crash::default();
+    // Tombstones go on to list every other thread, which we skip.
+    let mut other_threads = false;
+    let header = regex!(r"^(pid: \d+, tid: \d+|signal \d+ \(|Abort message: |Cmdline: )");
+    let lines = logcat_parser::parse(text)
+        .into_iter()
+        .flat_map(|entry| match entry {
+            Entry::Record(record) => record
+                .message
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect::<Vec<_>>(),
+            Entry::Unparsed { unparsed } => vec![unparsed],
+        })
+        .collect::<Vec<_>>();
+    for line in &lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with("*** *** ***") {
+            if !current.frames.is_empty() {
+                crashes.push(std::mem::take(&mut current));
+            }
+            current.header.clear();
+            other_threads = false;
+        } else if trimmed.starts_with("--- --- ---") {
+            other_threads = true;
+        } else if other_threads {
+            continue;
+        } else if let Some(frame) = Frame::from_line(trimmed) {
+            if frame.index == 0 && !current.frames.is_empty() {
+                crashes.push(Crash {
+                    header: current.header.clone(),
+                    frames: std::mem::take(&mut current.frames),
+                });
+            }
+            current.frames.push(frame);
+        } else if header.is_match(trimmed) {
+            current.header.push(trimmed.to_owned());
+        }
+    }
+    if !current.frames.is_empty() {
+        crashes.push(current);
+    }
+    crashes
+}
+
+/// Reads the GNU build ID note of an ELF file.
+pub fn read_build_id(path: &Path) -> Option<String> {
+    fn read_at(file: &mut File, offset: u64, len: u64) -> Option<Vec<u8>> {
+        // Garbage lengths shouldn't get to allocate, so they're checked
+        // against the file first.
+        if offset.checked_add(len)? > file.metadata().ok()?.len() {
+            return None;
+        }
+        let mut buf = vec![0; len as usize];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut buf).ok()?;
+        Some(buf)
+    }
+
+    fn uint(bytes: &[u8]) -> u64 {
+        // Android is little-endian across the board
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |acc, byte| (acc << 8) | u64::from(*byte))
+    }
+
+    const PT_NOTE: u64 = 4;
+    const NT_GNU_BUILD_ID: u64 = 3;
+
+    let mut file = File::open(path).ok()?;
+    let ident = read_at(&mut file, 0, 64)?;
+    if &ident[..4] != b"\x7fELF" || ident[5] != 1 {
+        return None;
+    }
+    let is_64 = ident[4] == 2;
+    let min_phentsize = if is_64 { 0x38 } else { 0x20 };
+    let (phoff, phentsize, phnum) = if is_64 {
+        (
+            uint(&ident[0x20..0x28]),
+            uint(&ident[0x36..0x38]),
+            uint(&ident[0x38..0x3a]),
+        )
+    } else {
+        (
+            uint(&ident[0x1c..0x20]),
+            uint(&ident[0x2a..0x2c]),
+            uint(&ident[0x2c..0x2e]),
+        )
+    };
+    if phentsize < min_phentsize {
+        return None;
+    }
+    for index in 0..phnum {
+        let header_offset = index
+            .checked_mul(phentsize)
+            .and_then(|offset| phoff.checked_add(offset))?;
+        let header = read_at(&mut file, header_offset, phentsize)?;
+        if uint(&header[0..4]) != PT_NOTE {
+            continue;
+        }
+        let (offset, size) = if is_64 {
+            (uint(&header[0x08..0x10]), uint(&header[0x20..0x28]))
+        } else {
+            (uint(&header[0x04..0x08]), uint(&header[0x10..0x14]))
+        };
+        let notes = read_at(&mut file, offset, size)?;
+        let mut notes = notes.as_slice();
+        while notes.len() >= 12 {
+            let name_size = uint(&notes[0..4]) as usize;
+            let desc_size = uint(&notes[4..8]) as usize;
+            let kind = uint(&notes[8..12]);
+            let desc_start = 12 + ((name_size + 3) & !3);
+            let desc_end = desc_start + desc_size;
+            if notes.len() < desc_end {
+                break;
+            }
+            if kind == NT_GNU_BUILD_ID && &notes[12..12 + name_size] == b"GNU\0" {
+                return Some(
+                    notes[desc_start..desc_end]
+                        .iter()
+                        .map(|byte| format!("{:02x}", byte))
+                        .collect(),
+                );
+            }
+            notes = notes.get((desc_end + 3) & !3..).unwrap_or_default();
+        }
+    }
+    None
+}
+
+/// A source location, as resolved by `llvm-symbolizer`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl Location {
+    fn from_lines(function: &str, location: &str) -> Self {
+        let mut parts = location.rsplitn(3, ':');
+        let (column, line, file) = (parts.next(), parts.next(), parts.next());
+        let (file, line, column) = match file {
+            Some(file) => (file, line, column),
+            // Older versions leave out the column
+            None => (line.unwrap_or_default(), column, None),
+        };
+        Self {
+            function: (function != "??").then(|| demangle(function)),
+            file: (file != "??").then(|| file.to_owned()),
+            line: line
+                .and_then(|line| line.parse().ok())
+                .filter(|line| *line != 0),
+            column: column
+                .and_then(|column| column.parse().ok())
+                .filter(|column| *column != 0),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Backtrace {
+    pub header: Vec<String>,
+    /// Every frame, along with its source locations (more than one when
+    /// functions were inlined).
+    pub frames: Vec<(Frame, Vec<Location>)>,
+}
+
+impl Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.header {
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(f, "stack backtrace:")?;
+        for (frame, locations) in &self.frames {
+            if locations.is_empty() {
+                let symbol = frame
+                    .symbol
+                    .as_deref()
+                    .map(demangle)
+                    .unwrap_or_else(|| "<unknown>".to_owned());
+                writeln!(f, "{:>4}: {}", frame.index, symbol)?;
+                writeln!(f, "             in {} (pc {:#x})", frame.path, frame.pc)?;
+                continue;
+            }
+            for location in locations {
+                writeln!(
+                    f,
+                    "{:>4}: {}",
+                    frame.index,
+                    location.function.as_deref().unwrap_or("<unknown>")
+                )?;
+                match (&location.file, location.line) {
+                    (Some(file), Some(line)) => {
+                        write!(f, "             at {}:{}", file, line)?;
+                        match location.column {
+                            Some(column) => writeln!(f, ":{}", column)?,
+                            None => writeln!(f)?,
+                        }
+                    }
+                    _ => writeln!(f, "             in {} (pc {:#x})", frame.path, frame.pc)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where to look for unstripped copies of the app's libraries for `target`:
+/// both profiles' build output, then whatever is currently in `jniLibs`.
+/// Release builds link stripped copies into `jniLibs`, so the build output
+/// has to come first.
+pub fn lib_dirs(config: &Config, target: &Target<'_>) -> Vec<PathBuf> {
+    let app = config.app();
+    vec![
+        app.target_dir(target.triple, Profile::Debug),
+        app.target_dir(target.triple, Profile::Release),
+        jnilibs::path(config, *target),
+    ]
+}
+
+/// Resolves frames in our own libraries using their unstripped copies on the
+/// host.
+#[derive(Debug)]
+pub struct Symbolicator {
+    symbolizer: PathBuf,
+    lib_dirs: Vec<PathBuf>,
+}
+
+impl Symbolicator {
+    /// `lib_dirs` are searched in order for libraries with matching names.
+    pub fn new(ndk: &ndk::Env, lib_dirs: Vec<PathBuf>) -> Result<Self, Error> {
+        Ok(Self {
+            symbolizer: ndk.symbolizer_path()?,
+            lib_dirs,
+        })
+    }
+
+    /// Finds the library a frame belongs to, preferring one with a matching
+    /// build ID so a stale build doesn't produce misleading line numbers.
+    fn find_lib(&self, name: &str, build_id: Option<&str>) -> Option<PathBuf> {
+        let candidates = self
+            .lib_dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .filter(|path| path.is_file() && !symbols::is_stripped(path))
+            .collect::<Vec<_>>();
+        match build_id {
+            Some(expected) => {
+                let build_ids = candidates
+                    .into_iter()
+                    .map(|path| (read_build_id(&path), path))
+                    .collect::<Vec<_>>();
+                build_ids
+                    .iter()
+                    .find(|(actual, _)| actual.as_deref() == Some(expected))
+                    .or_else(|| build_ids.iter().find(|(actual, _)| actual.is_none()))
+                    .map(|(_, path)| path.clone())
+            }
+            None => candidates.into_iter().reduce(last_modified),
+        }
+    }
+
+    fn symbolize(&self, lib: &Path, pcs: &[u64]) -> Result<Vec<Vec<Location>>, Error> {
+        let mut args = vec!["--inlining".to_owned(), "--functions=linkage".to_owned()];
+        args.push(format!("--obj={}", lib.display()));
+        args.extend(pcs.iter().map(|pc| format!("{:#x}", pc)));
+        let output = duct::cmd(&self.symbolizer, args)
+            .stderr_null()
+            .read()
+            .map_err(|source| Error::SymbolizerFailed {
+                lib: lib.to_owned(),
+                source,
+            })?;
+        // Each address gets pairs of function and location lines (one pair per
+        // inlined frame), followed by a blank line.
+        let mut results = output
+            .split("\n\n")
+            .map(|block| {
+                let lines = block.lines().collect::<Vec<_>>();
+                lines
+                    .chunks(2)
+                    .filter(|pair| pair.len() == 2)
+                    .map(|pair| Location::from_lines(pair[0].trim(), pair[1].trim()))
+                    .filter(|location| location.function.is_some() || location.file.is_some())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        results.resize(pcs.len(), Vec::new());
+        Ok(results)
+    }
+
+    pub fn symbolicate(&self, crash: &Crash) -> Result<Backtrace, Error> {
+        let mut frames = crash
+            .frames
+            .iter()
+            .map(|frame| (frame.clone(), Vec::new()))
+            .collect::<Vec<_>>();
+        let mut by_lib = BTreeMap::<PathBuf, Vec<usize>>::new();
+        for (index, frame) in crash.frames.iter().enumerate() {
+            if let Some(lib) = self.find_lib(frame.library(), frame.build_id.as_deref()) {
+                by_lib.entry(lib).or_default().push(index);
+            }
+        }
+        for (lib, indices) in by_lib {
+            let pcs = indices
+                .iter()
+                .map(|index| crash.frames[*index].pc)
+                .collect::<Vec<_>>();
+            for (index, locations) in indices.into_iter().zip(self.symbolize(&lib, &pcs)?) {
+                frames[index].1 = locations;
+            }
+        }
+        Ok(Backtrace {
+            header: crash.header.clone(),
+            frames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(line, expected,
+        case(
+            "      #00 pc 000000000004f0e8  /data/app/~~Xw==/com.example.app-Yw==/lib/arm64/libexample.so (BuildId: 1F2E3D)",
+            Frame { index: 0, pc: 0x4f0e8, path: "/data/app/~~Xw==/com.example.app-Yw==/lib/arm64/libexample.so".into(), symbol: None, build_id: Some("1f2e3d".into()) },
+        ),
+        case(
+            "      #01 pc 00000000000d5e3c  /data/app/com.example.app-1/base.apk!libexample.so (offset 0x1000) (_ZN7example4boom17h0123456789abcdefE+24) (BuildId: abcd)",
+            Frame { index: 1, pc: 0xd5e3c, path: "/data/app/com.example.app-1/base.apk!libexample.so".into(), symbol: Some("_ZN7example4boom17h0123456789abcdefE".into()), build_id: Some("abcd".into()) },
+        ),
+        case(
+            "      #02 pc 0000000000089a1c  /apex/com.android.runtime/lib64/bionic/libc.so (abort+164)",
+            Frame { index: 2, pc: 0x89a1c, path: "/apex/com.android.runtime/lib64/bionic/libc.so".into(), symbol: Some("abort".into()), build_id: None },
+        ),
+        case(
+            "      #03 pc 000000000012c0d8  /system/lib64/libutils.so (android::Looper::pollOnce(int, int*, int*, void**)+80) (BuildId: 5ae0)",
+            Frame { index: 3, pc: 0x12c0d8, path: "/system/lib64/libutils.so".into(), symbol: Some("android::Looper::pollOnce(int, int*, int*, void**)".into()), build_id: Some("5ae0".into()) },
+        ),
+    )]
+    fn test_parse_frame(line: &str, expected: Frame) {
+        assert_eq!(Frame::from_line(line), Some(expected));
+    }
+
+    fn elf64(phentsize: u16, notes: &[u8]) -> Vec<u8> {
+        let mut elf = vec![0; 64 + 56];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        elf[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+        elf[0x36..0x38].copy_from_slice(&phentsize.to_le_bytes());
+        elf[0x38..0x3a].copy_from_slice(&1u16.to_le_bytes());
+        elf[64..68].copy_from_slice(&4u32.to_le_bytes());
+        elf[64 + 0x08..64 + 0x10].copy_from_slice(&120u64.to_le_bytes());
+        elf[64 + 0x20..64 + 0x28].copy_from_slice(&(notes.len() as u64).to_le_bytes());
+        elf.extend_from_slice(notes);
+        elf
+    }
+
+    fn with_phoff(mut elf: Vec<u8>, phoff: u64) -> Vec<u8> {
+        elf[0x20..0x28].copy_from_slice(&phoff.to_le_bytes());
+        elf
+    }
+
+    fn note(kind: u32, name: &[u8], desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&kind.to_le_bytes());
+        note.extend_from_slice(name);
+        note.resize((note.len() + 3) & !3, 0);
+        note.extend_from_slice(desc);
+        note
+    }
+
+    #[rstest(elf, expected,
+        case(elf64(56, &note(3, b"GNU\0", &[0x1f, 0x2e, 0x3d])), Some("1f2e3d")),
+        case(elf64(56, &[note(1, b"GNU\0", &[0; 8]), note(3, b"GNU\0", &[0xab])].concat()), Some("ab")),
+        // the last note's padding runs past the segment
+        case(elf64(56, &note(1, b"GNU\0", &[0])), None),
+        case(elf64(8, &note(3, b"GNU\0", &[0xab])), None),
+        case(elf64(0xffff, &note(3, b"GNU\0", &[0xab])), None),
+        case(elf64(56, &[]).into_iter().take(100).collect(), None),
+        case(with_phoff(elf64(56, &[]), u64::MAX), None),
+    )]
+    fn test_read_build_id(elf: Vec<u8>, expected: Option<&str>) {
+        static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "cargo-mobile2-build-id-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        std::fs::write(&path, &elf).unwrap();
+        let actual = read_build_id(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(actual.as_deref(), expected);
+    }
+
+    #[test]
+    fn test_parse_logcat() {
+        let crashes = parse(include_str!("adb/fixtures/logcat/crash.txt"));
+        assert_eq!(crashes.len(), 1);
+        assert_eq!(
+            crashes[0].header,
+            vec![
+                "pid: 4242, tid: 4260, name: Thread-2  >>> com.example.app <<<",
+                "signal 6 (SIGABRT), code -1 (SI_QUEUE), fault addr --------",
+                "Abort message: 'attempt to divide by zero'",
+            ]
+        );
+        assert_eq!(
+            crashes[0]
+                .frames
+                .iter()
+                .map(|frame| frame.library())
+                .collect::<Vec<_>>(),
+            vec!["libc.so", "libexample.so", "libexample.so"]
+        );
+    }
+
+    #[rstest(
+        symbol,
+        expected,
+        case("_ZN7example4boom17h0123456789abcdefE", "example::boom"),
+        case("example::boom::h0123456789abcdef", "example::boom"),
+        case("__libc_init", "__libc_init")
+    )]
+    fn test_demangle(symbol: &str, expected: &str) {
+        assert_eq!(demangle(symbol), expected);
+    }
+}
@@ -0,0 +1,208 @@
+//! Splitting native debug symbols out of release libraries, so the APK/AAB
+//! ships stripped libraries and the symbols can be uploaded to the Play
+//! Console separately.
+
+use super::{config::Config, ndk, target::Target};
+use crate::{
+    opts::Profile,
+    util::cli::{Report, Reportable},
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+pub static ARCHIVE_NAME: &str = "native-debug-symbols.zip";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    MissingTool(ndk::MissingToolError),
+    #[error("Failed to strip {path:?}: {source}")]
+    StripFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to read {path:?}: {source}")]
+    ReadFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to write {path:?}: {source}")]
+    WriteFailed { path: PathBuf, source: io::Error },
+    #[error("Failed to write {path:?}: {source}")]
+    ZipFailed {
+        path: PathBuf,
+        source: zip::result::ZipError,
+    },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::MissingTool(err) => Report::error("Failed to locate `llvm-strip`", err),
+            Self::StripFailed { .. } => Report::error("Failed to strip debug info", self),
+            Self::ReadFailed { .. } | Self::WriteFailed { .. } | Self::ZipFailed { .. } => {
+                Report::error("Failed to archive native debug symbols", self)
+            }
+        }
+    }
+}
+
+/// Where the stripped copy of a library goes. The unstripped original stays
+/// where cargo put it, so local symbolication keeps working.
+pub fn stripped_path(lib_path: &Path) -> PathBuf {
+    let file_name = lib_path
+        .file_name()
+        .expect("developer error: lib path had no file name");
+    lib_path.with_file_name("stripped").join(file_name)
+}
+
+/// Whether `lib_path` is (or links to) a stripped copy, which is useless for
+/// symbolication even though it keeps its build ID.
+pub fn is_stripped(lib_path: &Path) -> bool {
+    let lib_path = fs::canonicalize(lib_path).unwrap_or_else(|_| lib_path.to_owned());
+    lib_path
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|dir| dir == "stripped")
+}
+
+/// Writes a copy of `lib_path` without debug info and returns its path.
+pub fn strip(ndk: &ndk::Env, lib_path: &Path) -> Result<PathBuf, Error> {
+    let dest = stripped_path(lib_path);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|source| Error::WriteFailed {
+            path: parent.to_owned(),
+            source,
+        })?;
+    }
+    duct::cmd(
+        ndk.strip_path().map_err(Error::MissingTool)?,
+        [
+            "--strip-unneeded".as_ref(),
+            "-o".as_ref(),
+            dest.as_os_str(),
+            lib_path.as_os_str(),
+        ],
+    )
+    .stdout_capture()
+    .run()
+    .map_err(|source| Error::StripFailed {
+        path: lib_path.to_owned(),
+        source,
+    })?;
+    Ok(dest)
+}
+
+/// Where the symbol archive for a bundle goes, i.e. right next to it.
+pub fn archive_path(aab_path: &Path) -> PathBuf {
+    aab_path.with_file_name(ARCHIVE_NAME)
+}
+
+/// Zips the unstripped libraries into `<abi>/<lib>` entries, which is the
+/// layout the Play Console expects.
+pub fn write_archive(
+    config: &Config,
+    profile: Profile,
+    targets: &[&Target],
+    dest: &Path,
+) -> Result<(), Error> {
+    let libs = targets
+        .iter()
+        .filter_map(|target| {
+            let lib_path = config
+                .app()
+                .target_dir(target.triple, profile)
+                .join(config.so_name());
+            if lib_path.is_file() {
+                Some((target.abi, lib_path))
+            } else {
+                log::info!("no lib at {:?}, so no symbols for {}", lib_path, target.abi);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    write_zip(&libs, dest)
+}
+
+fn write_zip(libs: &[(&str, PathBuf)], dest: &Path) -> Result<(), Error> {
+    let write_failed = |source| Error::WriteFailed {
+        path: dest.to_owned(),
+        source,
+    };
+    let zip_failed = |source| Error::ZipFailed {
+        path: dest.to_owned(),
+        source,
+    };
+    let mut zip = ZipWriter::new(fs::File::create(dest).map_err(write_failed)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (abi, lib_path) in libs {
+        let file_name = lib_path
+            .file_name()
+            .expect("developer error: lib path had no file name")
+            .to_string_lossy();
+        zip.start_file(format!("{}/{}", abi, file_name), options)
+            .map_err(zip_failed)?;
+        fs::File::open(lib_path)
+            .and_then(|mut lib| io::copy(&mut lib, &mut zip))
+            .map_err(|source| Error::ReadFailed {
+                path: lib_path.clone(),
+                source,
+            })?;
+    }
+    zip.finish().map_err(zip_failed)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read as _;
+
+    #[test]
+    fn strips_into_sibling_dir() {
+        let lib = Path::new("target/aarch64-linux-android/release/libapp.so");
+        let stripped = stripped_path(lib);
+        assert_eq!(
+            stripped,
+            Path::new("target/aarch64-linux-android/release/stripped/libapp.so")
+        );
+        assert!(is_stripped(&stripped));
+        assert!(!is_stripped(lib));
+        assert_eq!(
+            archive_path(Path::new("build/outputs/bundle/release/app.aab")),
+            Path::new("build/outputs/bundle/release").join(ARCHIVE_NAME)
+        );
+    }
+
+    #[test]
+    fn lays_out_archive_by_abi() {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-mobile2-symbols-{}", std::process::id()));
+        for abi in ["arm64-v8a", "x86_64"] {
+            fs::create_dir_all(dir.join(abi)).unwrap();
+            fs::write(dir.join(abi).join("libapp.so"), abi).unwrap();
+        }
+        let dest = dir.join(ARCHIVE_NAME);
+        write_zip(
+            &[
+                ("arm64-v8a", dir.join("arm64-v8a/libapp.so")),
+                ("x86_64", dir.join("x86_64/libapp.so")),
+            ],
+            &dest,
+        )
+        .unwrap();
+        let mut archive = zip::ZipArchive::new(fs::File::open(&dest).unwrap()).unwrap();
+        let mut names = archive
+            .file_names()
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["arm64-v8a/libapp.so", "x86_64/libapp.so"]);
+        let mut contents = String::new();
+        archive
+            .by_name("x86_64/libapp.so")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "x86_64");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,7 +2,7 @@ use super::{
     config::{Config, Metadata},
     env::Env,
     jnilibs::{self, JniLibs},
-    ndk,
+    ndk, symbols,
 };
 use crate::{
     dot_cargo::DotCargoTarget,
@@ -12,16 +12,27 @@ use crate::{
         cli::{Report, Reportable},
         CargoCommand,
     },
+    DuctExpressionExt,
 };
 use once_cell_regex::exports::once_cell::sync::OnceCell;
 use serde::Serialize;
-use std::{collections::BTreeMap, fmt, io, path::PathBuf, str};
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    str,
+};
 use thiserror::Error;
 
+/// What cargo runs executables built for Android targets with.
+pub static RUNNER: &str = "cargo-android runner";
+
 #[derive(Clone, Copy, Debug)]
 pub enum CargoMode {
     Check,
     Build,
+    Test,
+    Bench,
 }
 
 impl fmt::Display for CargoMode {
@@ -29,6 +40,8 @@ impl fmt::Display for CargoMode {
         match self {
             CargoMode::Check => write!(f, "check"),
             CargoMode::Build => write!(f, "build"),
+            CargoMode::Test => write!(f, "test"),
+            CargoMode::Bench => write!(f, "bench"),
         }
     }
 }
@@ -38,14 +51,32 @@ impl CargoMode {
         match self {
             CargoMode::Check => "check",
             CargoMode::Build => "build",
+            CargoMode::Test => "test",
+            CargoMode::Bench => "bench",
         }
     }
 }
 
+#[derive(Debug, Error)]
+#[error("Building for {triple} requires NDK {you_need} or newer, since older NDKs don't have its toolchain (you currently have NDK {you_have})")]
+pub struct NdkTooOldError {
+    pub triple: String,
+    pub you_have: ndk::NdkVersion,
+    pub you_need: ndk::NdkVersion,
+}
+
+impl Reportable for NdkTooOldError {
+    fn report(&self) -> Report {
+        Report::action_request("Installed NDK is too old for this target", self)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum CompileLibError {
     #[error("Failed to locate required build tool: {0}")]
     MissingTool(ndk::MissingToolError),
+    #[error(transparent)]
+    NdkTooOld(NdkTooOldError),
     #[error("`Failed to run `cargo {mode}`: {cause}")]
     CargoFailed {
         mode: CargoMode,
@@ -53,11 +84,19 @@ pub enum CompileLibError {
     },
     #[error("`Failed to write file at {path} : {cause}")]
     FileWrite { path: PathBuf, cause: io::Error },
+    #[error("`cargo {mode}` printed a message that wasn't JSON: {cause}")]
+    MessageInvalid {
+        mode: CargoMode,
+        cause: serde_json::Error,
+    },
 }
 
 impl Reportable for CompileLibError {
     fn report(&self) -> Report {
-        Report::error("Failed to compile lib", self)
+        match self {
+            Self::NdkTooOld(err) => err.report(),
+            _ => Report::error("Failed to compile lib", self),
+        }
     }
 }
 
@@ -71,13 +110,18 @@ pub enum SymlinkLibsError {
     RequiredLibsFailed(ndk::RequiredLibsError),
     #[error("Failed to locate \"libc++_shared.so\": {0}")]
     LibcxxSharedPathFailed(ndk::MissingToolError),
+    #[error(transparent)]
+    StripFailed(symbols::Error),
     #[error("Library artifact not found at {path}. Make sure your Cargo.toml file has a [lib] block with `crate-type = [\"staticlib\", \"cdylib\", \"rlib\"]`")]
     LibNotFound { path: PathBuf },
 }
 
 impl Reportable for SymlinkLibsError {
     fn report(&self) -> Report {
-        Report::error("Failed to symlink lib", self)
+        match self {
+            Self::StripFailed(err) => err.report(),
+            _ => Report::error("Failed to symlink lib", self),
+        }
     }
 }
 
@@ -98,6 +142,21 @@ impl Reportable for BuildError {
     }
 }
 
+/// Picks the test (or bench) executables out of the JSON messages printed by
+/// `cargo test --no-run`, which also lists every other artifact it built.
+fn test_executables(output: &str) -> Result<Vec<PathBuf>, serde_json::Error> {
+    let mut executables = Vec::new();
+    for line in output.lines().filter(|line| line.starts_with('{')) {
+        let message = serde_json::from_str::<serde_json::Value>(line)?;
+        let is_test =
+            message["reason"] == "compiler-artifact" && message["profile"]["test"] == true;
+        if let Some(executable) = message["executable"].as_str().filter(|_| is_test) {
+            executables.push(PathBuf::from(executable));
+        }
+    }
+    Ok(executables)
+}
+
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct Target<'a> {
     pub triple: &'a str,
@@ -105,6 +164,18 @@ pub struct Target<'a> {
     binutils_triple_override: Option<&'a str>,
     pub abi: &'a str,
     pub arch: &'a str,
+    /// The NDK only ships this target's toolchain starting from this API
+    /// level, so the library is built against it even if the app's
+    /// `minSdkVersion` is lower.
+    #[serde(skip)]
+    min_api: Option<u32>,
+    /// The first NDK to ship this target's toolchain.
+    #[serde(skip)]
+    min_ndk_version: Option<ndk::NdkVersion>,
+    /// Whether rustup ships a standard library for this target. Targets that
+    /// need `-Zbuild-std` are only enabled when listed in `android.targets`.
+    #[serde(skip)]
+    prebuilt_std: bool,
 }
 
 impl<'a> TargetTrait<'a> for Target<'a> {
@@ -122,6 +193,9 @@ impl<'a> TargetTrait<'a> for Target<'a> {
                     binutils_triple_override: None,
                     abi: "arm64-v8a",
                     arch: "arm64",
+                    min_api: None,
+                    min_ndk_version: None,
+                    prebuilt_std: true,
                 },
             );
             targets.insert(
@@ -132,6 +206,9 @@ impl<'a> TargetTrait<'a> for Target<'a> {
                     binutils_triple_override: Some("arm-linux-androideabi"),
                     abi: "armeabi-v7a",
                     arch: "arm",
+                    min_api: None,
+                    min_ndk_version: None,
+                    prebuilt_std: true,
                 },
             );
             targets.insert(
@@ -142,6 +219,9 @@ impl<'a> TargetTrait<'a> for Target<'a> {
                     binutils_triple_override: None,
                     abi: "x86",
                     arch: "x86",
+                    min_api: None,
+                    min_ndk_version: None,
+                    prebuilt_std: true,
                 },
             );
             targets.insert(
@@ -152,6 +232,22 @@ impl<'a> TargetTrait<'a> for Target<'a> {
                     binutils_triple_override: None,
                     abi: "x86_64",
                     arch: "x86_64",
+                    min_api: None,
+                    min_ndk_version: None,
+                    prebuilt_std: true,
+                },
+            );
+            targets.insert(
+                "riscv64",
+                Target {
+                    triple: "riscv64-linux-android",
+                    clang_triple_override: None,
+                    binutils_triple_override: None,
+                    abi: "riscv64",
+                    arch: "riscv64",
+                    min_api: Some(35),
+                    min_ndk_version: Some(ndk::NdkVersion::new(27, 0)),
+                    prebuilt_std: false,
                 },
             );
             targets
@@ -180,6 +276,54 @@ impl<'a> Target<'a> {
         self.binutils_triple_override.unwrap_or(self.triple)
     }
 
+    /// The API level the library is built against.
+    fn min_sdk_version(&self, config: &Config) -> u32 {
+        let min_sdk_version = config.min_sdk_version();
+        self.min_api
+            .map_or(min_sdk_version, |min_api| min_api.max(min_sdk_version))
+    }
+
+    pub fn prebuilt_std(&self) -> bool {
+        self.prebuilt_std
+    }
+
+    /// Installs the enabled targets with rustup.
+    pub fn install_enabled(config: &Config) -> Result<(), std::io::Error> {
+        for target in config.targets() {
+            if target.prebuilt_std {
+                target.install()?;
+            } else {
+                log::warn!(
+                    "rustup doesn't ship a standard library for `{}`; building for it requires nightly Rust with `-Zbuild-std`",
+                    target.triple
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_ndk(&self, ndk: &ndk::Env) -> Result<(), NdkTooOldError> {
+        let you_have = ndk::NdkVersion::from(ndk.version().unwrap_or_default());
+        match self.min_ndk_version {
+            Some(you_need) if you_have < you_need => Err(NdkTooOldError {
+                triple: self.triple.to_owned(),
+                you_have,
+                you_need,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The key this target goes by in `android.targets` and on the command
+    /// line, i.e. `aarch64`.
+    pub fn name(&'a self) -> &'a str {
+        Self::all()
+            .iter()
+            .find(|(_, target)| *target == self)
+            .map(|(name, _)| *name)
+            .expect("developer error: target wasn't in `Target::all`")
+    }
+
     pub fn for_abi(abi: &str) -> Option<&'a Self> {
         Self::all().values().find(|target| target.abi == abi)
     }
@@ -190,6 +334,7 @@ impl<'a> Target<'a> {
             "arm64" => "Arm64",
             "x86_64" => "X86_64",
             "x86" => "X86",
+            "riscv64" => "Riscv64",
             arch => arch,
         }
     }
@@ -205,7 +350,7 @@ impl<'a> Target<'a> {
             .compiler_path(
                 ndk::Compiler::Clang,
                 self.clang_triple(),
-                config.min_sdk_version(),
+                self.min_sdk_version(config),
             )?
             .display()
             .to_string();
@@ -221,11 +366,14 @@ impl<'a> Target<'a> {
                 "-Clink-arg=-llog".to_owned(),
                 "-Clink-arg=-lOpenSLES".to_owned(),
             ],
+            // Lets plain `cargo run`/`cargo test` run things on a device
+            runner: Some(RUNNER.to_owned()),
         })
     }
 
+    /// `cargo <mode>` for this target, with the NDK toolchain set up.
     #[allow(clippy::too_many_arguments)]
-    fn compile_lib(
+    fn cargo_command(
         &self,
         config: &Config,
         metadata: &Metadata,
@@ -234,13 +382,15 @@ impl<'a> Target<'a> {
         force_color: bool,
         profile: Profile,
         mode: CargoMode,
-    ) -> Result<(), CompileLibError> {
-        let min_sdk_version = config.min_sdk_version();
+    ) -> Result<duct::Expression, CompileLibError> {
+        self.check_ndk(&env.ndk)
+            .map_err(CompileLibError::NdkTooOld)?;
+        let min_sdk_version = self.min_sdk_version(config);
 
         // Force color, since gradle would otherwise give us uncolored output
         // (which Android Studio makes red, which is extra gross!)
         let color = if force_color { "always" } else { "auto" };
-        CargoCommand::new(mode.as_str())
+        Ok(CargoCommand::new(mode.as_str())
             .with_verbose(noise_level.pedantic())
             .with_package(Some(config.app().name()))
             .with_manifest_path(Some(config.app().manifest_path()))
@@ -249,7 +399,7 @@ impl<'a> Target<'a> {
             .with_args(metadata.cargo_args())
             .with_features(metadata.features())
             .with_release(profile.release())
-            .build(env)
+            .build_without_stdio(env)
             .env("ANDROID_NATIVE_API_LEVEL", min_sdk_version.to_string())
             .env(
                 "TARGET_AR",
@@ -272,10 +422,103 @@ impl<'a> Target<'a> {
             .before_spawn(move |cmd| {
                 cmd.args(["--color", color]);
                 Ok(())
+            }))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn compile_lib(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+        force_color: bool,
+        profile: Profile,
+        mode: CargoMode,
+    ) -> Result<(), CompileLibError> {
+        self.cargo_command(
+            config,
+            metadata,
+            env,
+            noise_level,
+            force_color,
+            profile,
+            mode,
+        )?
+        .dup_stdio()
+        .run()
+        .map_err(|cause| CompileLibError::CargoFailed { mode, cause })?;
+        Ok(())
+    }
+
+    /// Builds test or bench executables without running them, and returns
+    /// their paths. `cargo bench` picks its own `bench` profile and rejects
+    /// `--release`, so benches are built with [`Profile::Debug`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn build_tests(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        env: &Env,
+        noise_level: NoiseLevel,
+        force_color: bool,
+        profile: Profile,
+        mode: CargoMode,
+    ) -> Result<Vec<PathBuf>, CompileLibError> {
+        // Build output still goes to stderr, so only the JSON is captured
+        let output = self
+            .cargo_command(
+                config,
+                metadata,
+                env,
+                noise_level,
+                force_color,
+                profile,
+                mode,
+            )?
+            .before_spawn(|cmd| {
+                cmd.args(["--no-run", "--message-format=json-render-diagnostics"]);
+                Ok(())
             })
-            .run()
+            .read()
             .map_err(|cause| CompileLibError::CargoFailed { mode, cause })?;
-        Ok(())
+        test_executables(&output).map_err(|cause| CompileLibError::MessageInvalid { mode, cause })
+    }
+
+    /// Shared libraries `elf` needs that aren't part of Android itself, i.e.
+    /// `libc++_shared.so` or other libraries from the same build.
+    pub fn bundled_libs(
+        &self,
+        ndk: &ndk::Env,
+        elf: &Path,
+    ) -> Result<Vec<PathBuf>, SymlinkLibsError> {
+        let search_dirs = elf
+            .ancestors()
+            .skip(1)
+            .take(2)
+            .map(Path::to_owned)
+            .collect::<Vec<_>>();
+        let mut libs = Vec::new();
+        for lib in ndk
+            .required_libs(elf, self.binutils_triple())
+            .map_err(SymlinkLibsError::RequiredLibsFailed)?
+        {
+            if lib == "libc++_shared.so" {
+                libs.push(
+                    ndk.libcxx_shared_path(*self)
+                        .map_err(SymlinkLibsError::LibcxxSharedPathFailed)?,
+                );
+            } else if let Some(path) = search_dirs
+                .iter()
+                .map(|dir| dir.join(&lib))
+                .find(|path| path.is_file())
+            {
+                libs.push(path);
+            } else {
+                log::info!("assuming {:?} is a system library", lib);
+            }
+        }
+        Ok(libs)
     }
 
     pub fn check(
@@ -315,8 +558,15 @@ impl<'a> Target<'a> {
             return Err(SymlinkLibsError::LibNotFound { path: src });
         }
 
+        // Release builds ship without debug info; the symbols are archived
+        // separately when bundling
+        let lib = if profile.release() {
+            symbols::strip(ndk, &src).map_err(SymlinkLibsError::StripFailed)?
+        } else {
+            src.clone()
+        };
         jnilibs
-            .symlink_lib(&src)
+            .symlink_lib(&lib)
             .map_err(SymlinkLibsError::SymlinkFailed)?;
 
         let needs_cxx_shared = ndk
@@ -359,3 +609,26 @@ impl<'a> Target<'a> {
             .map_err(BuildError::SymlinkLibsFailed)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_test_executables() {
+        assert_eq!(
+            test_executables(include_str!("fixtures/test_no_run.json")).unwrap(),
+            [
+                "/home/user/app/target/aarch64-linux-android/debug/deps/smoke-e252a5732ab268d1",
+                "/home/user/app/target/aarch64-linux-android/debug/deps/tool-bae01ad8e4f8c13b",
+                "/home/user/app/target/aarch64-linux-android/debug/deps/app-da7bd49172ab2c0f",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_test_executables_invalid() {
+        assert!(test_executables("{\"reason\":").is_err());
+    }
+}
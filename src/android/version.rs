@@ -0,0 +1,221 @@
+//! Deriving `versionName` and `versionCode` from the crate version.
+
+use crate::util::cli::{Report, Reportable};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+pub static DEFAULT_VERSION_CODE_FORMULA: &str = "major * 1000000 + minor * 1000 + patch";
+// Google Play rejects anything larger
+const MAX_VERSION_CODE: u64 = 2100000000;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to read {path:?}: {source}")]
+    ReadFailed {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse {path:?}: {source}")]
+    ParseFailed {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("{path:?} inherits its version from a workspace, but no workspace sets `workspace.package.version`")]
+    WorkspaceVersionMissing { path: PathBuf },
+    #[error("{0:?} isn't a `major.minor.patch` version")]
+    VersionInvalid(String),
+    #[error("Version code formula {formula:?} is invalid: {reason}")]
+    FormulaInvalid { formula: String, reason: String },
+    #[error("Version code {code} computed from {version:?} is larger than the maximum of {MAX_VERSION_CODE}")]
+    CodeTooLarge { version: String, code: u64 },
+}
+
+impl Reportable for Error {
+    fn report(&self) -> Report {
+        match self {
+            Self::ReadFailed { .. } | Self::ParseFailed { .. } => {
+                Report::error("Failed to read crate version", self)
+            }
+            Self::WorkspaceVersionMissing { .. } | Self::VersionInvalid(_) => {
+                Report::error("Crate version invalid", self)
+            }
+            Self::FormulaInvalid { .. } | Self::CodeTooLarge { .. } => Report::action_request(
+                "Failed to compute version code",
+                format!(
+                    "{}. Change `version-code-formula` or set `version-code` in `[package.metadata.cargo-android]`.",
+                    self
+                ),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PackageVersion {
+    Version(String),
+    Inherited { workspace: bool },
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    package: Option<Package>,
+    workspace: Option<Workspace>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    version: Option<PackageVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Workspace {
+    package: Option<WorkspacePackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspacePackage {
+    version: Option<String>,
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest, Error> {
+    let contents = fs::read_to_string(path).map_err(|source| Error::ReadFailed {
+        path: path.to_owned(),
+        source,
+    })?;
+    toml::from_str(&contents).map_err(|source| Error::ParseFailed {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Reads `package.version` from a crate manifest, following
+/// `version.workspace = true` up to the workspace root. Crates without a
+/// version are `0.0.0`, like cargo treats them.
+pub fn crate_version(manifest_path: &Path) -> Result<String, Error> {
+    let manifest = read_manifest(manifest_path)?;
+    match manifest.package.and_then(|package| package.version) {
+        Some(PackageVersion::Version(version)) => Ok(version),
+        Some(PackageVersion::Inherited { workspace: true }) => manifest_path
+            .ancestors()
+            .skip(1)
+            .map(|dir| dir.join("Cargo.toml"))
+            .filter(|path| path.is_file())
+            .map(|path| read_manifest(&path))
+            .find_map(|manifest| {
+                manifest
+                    .map(|manifest| {
+                        manifest
+                            .workspace
+                            .and_then(|workspace| workspace.package)
+                            .and_then(|package| package.version)
+                    })
+                    .transpose()
+            })
+            .unwrap_or_else(|| {
+                Err(Error::WorkspaceVersionMissing {
+                    path: manifest_path.to_owned(),
+                })
+            }),
+        Some(PackageVersion::Inherited { workspace: false }) | None => Ok("0.0.0".to_owned()),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Triple {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Triple {
+    /// Parses the numeric part of a semver version, ignoring any pre-release
+    /// or build metadata.
+    pub fn parse(version: &str) -> Result<Self, Error> {
+        let invalid = || Error::VersionInvalid(version.to_owned());
+        let core = version
+            .split(['-', '+'])
+            .next()
+            .expect("developer error: split returned nothing");
+        let mut parts = core.split('.').map(|part| part.parse::<u64>());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Ok(Self {
+                major,
+                minor,
+                patch,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Evaluates a formula made of `major`, `minor`, `patch`, integers, `+` and
+/// `*` (i.e. `major * 10000 + minor * 100 + patch`).
+pub fn eval_formula(formula: &str, triple: Triple) -> Result<u64, Error> {
+    let invalid = |reason: String| Error::FormulaInvalid {
+        formula: formula.to_owned(),
+        reason,
+    };
+    let overflow = || invalid("the result overflowed".to_owned());
+    formula.split('+').try_fold(0u64, |sum, term| {
+        let product = term.split('*').try_fold(1u64, |product, factor| {
+            let value = match factor.trim() {
+                "major" => triple.major,
+                "minor" => triple.minor,
+                "patch" => triple.patch,
+                "" => return Err(invalid("it has an empty term".to_owned())),
+                number => number
+                    .parse()
+                    .map_err(|_| invalid(format!("{:?} isn't a number or version part", number)))?,
+            };
+            product.checked_mul(value).ok_or_else(overflow)
+        })?;
+        sum.checked_add(product).ok_or_else(overflow)
+    })
+}
+
+pub fn version_code(formula: &str, version: &str) -> Result<u32, Error> {
+    let code = eval_formula(formula, Triple::parse(version)?)?;
+    if code > MAX_VERSION_CODE {
+        return Err(Error::CodeTooLarge {
+            version: version.to_owned(),
+            code,
+        });
+    }
+    Ok(code as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest(
+        version,
+        formula,
+        code,
+        case("1.2.3", DEFAULT_VERSION_CODE_FORMULA, 1002003),
+        case("0.13.0-beta.2+build.5", DEFAULT_VERSION_CODE_FORMULA, 13000),
+        case("2.0.1", "major*10000+minor*100+patch", 20001),
+        case("4.5.6", "42", 42)
+    )]
+    fn computes_version_code(version: &str, formula: &str, code: u32) {
+        assert_eq!(version_code(formula, version).unwrap(), code);
+    }
+
+    #[rstest(
+        version,
+        formula,
+        case("1.2", DEFAULT_VERSION_CODE_FORMULA),
+        case("1.2.3", "major * build"),
+        case("1.2.3", "major +"),
+        case("3000.0.0", DEFAULT_VERSION_CODE_FORMULA)
+    )]
+    fn rejects_invalid_version_code(version: &str, formula: &str) {
+        assert!(version_code(formula, version).is_err());
+    }
+}
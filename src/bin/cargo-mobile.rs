@@ -2,6 +2,8 @@
 #![forbid(unsafe_code)]
 
 use cargo_mobile2::{
+    android::icon,
+    config::{Config, LoadOrGenError},
     doctor, init, update,
     util::{
         self,
@@ -93,6 +95,26 @@ pub enum Command {
         about = "Perform a check-up on your installation and environment"
     )]
     Doctor,
+    #[structopt(
+        name = "icon",
+        about = "Generates launcher icons for every density from a single image"
+    )]
+    Icon {
+        #[structopt(
+            name = "SOURCE",
+            help = "Square PNG to generate icons from, ideally at least 512x512",
+            parse(from_os_str)
+        )]
+        source: PathBuf,
+        #[structopt(
+            long = "background",
+            help = "Adaptive icon background color",
+            default_value = icon::DEFAULT_BACKGROUND
+        )]
+        background: String,
+        #[structopt(long = "force", help = "Replace icons even if they were customized")]
+        force: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -112,6 +134,8 @@ pub enum Error {
     AppleFailed(cargo_mobile2::apple::cli::Error),
     AndroidFailed(cargo_mobile2::android::cli::Error),
     DoctorFailed(doctor::Unrecoverable),
+    ConfigFailed(LoadOrGenError),
+    IconFailed(icon::Error),
 }
 
 impl Reportable for Error {
@@ -133,6 +157,8 @@ impl Reportable for Error {
             Self::AppleFailed(err) => err.report(),
             Self::AndroidFailed(err) => err.report(),
             Self::DoctorFailed(err) => Report::error("Failed to run doctor", err),
+            Self::ConfigFailed(err) => err.report(),
+            Self::IconFailed(err) => err.report(),
         }
     }
 }
@@ -229,6 +255,36 @@ impl Exec for Input {
                 .exec(wrapper)
                 .map_err(Error::AndroidFailed),
             Command::Doctor => doctor::exec(wrapper).map_err(Error::DoctorFailed),
+            Command::Icon {
+                source,
+                background,
+                force,
+            } => {
+                let (config, _origin) = Config::load_or_gen(".", non_interactive, wrapper)
+                    .map_err(Error::ConfigFailed)?;
+                let background = icon::parse_color(&background).map_err(Error::IconFailed)?;
+                let summary = icon::generate(config.android(), &source, background, force)
+                    .map_err(Error::IconFailed)?;
+                for path in &summary.written {
+                    println!("Wrote {}", path.display());
+                }
+                if !summary.skipped.is_empty() {
+                    Report::action_request(
+                        "Some launcher icons were customized, so they weren't replaced",
+                        format!(
+                            "{}\n\nPass `--force` to replace them anyway.",
+                            summary
+                                .skipped
+                                .iter()
+                                .map(|path| path.display().to_string())
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        ),
+                    )
+                    .print(wrapper);
+                }
+                Ok(())
+            }
         }
     }
 }
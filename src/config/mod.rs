@@ -138,19 +138,29 @@ impl Config {
         Ok(config)
     }
 
+    /// Loads the config of the project `cwd` is in, if it's in one.
+    pub fn load(cwd: impl AsRef<Path>) -> Result<Option<Self>, LoadOrGenError> {
+        Raw::load(cwd)
+            .map_err(LoadOrGenError::LoadFailed)?
+            .map(|(root_dir, raw)| {
+                Self::from_raw(root_dir.clone(), raw).map_err(|cause| {
+                    LoadOrGenError::FromRawFailed {
+                        path: root_dir,
+                        cause,
+                    }
+                })
+            })
+            .transpose()
+    }
+
     pub fn load_or_gen(
         cwd: impl AsRef<Path>,
         non_interactive: bool,
         wrapper: &TextWrapper,
     ) -> Result<(Self, Origin), LoadOrGenError> {
         let cwd = cwd.as_ref();
-        if let Some((root_dir, raw)) = Raw::load(cwd).map_err(LoadOrGenError::LoadFailed)? {
-            Self::from_raw(root_dir.clone(), raw)
-                .map(|config| (config, Origin::Loaded))
-                .map_err(|cause| LoadOrGenError::FromRawFailed {
-                    path: root_dir,
-                    cause,
-                })
+        if let Some(config) = Self::load(cwd)? {
+            Ok((config, Origin::Loaded))
         } else {
             Self::gen(cwd, non_interactive, wrapper)
                 .map(|config| (config, Origin::FreshlyMinted))
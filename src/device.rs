@@ -13,6 +13,15 @@ pub enum PromptErrorCause<T: Display> {
     PromptFailed(io::Error),
     #[error("No connected devices detected")]
     NoneDetected,
+    #[error("No connected device matched {0:?}")]
+    NoneMatched(String),
+    #[error("More than one connected device matched {selector:?}: {}", matches.join(", "))]
+    AmbiguousMatch {
+        selector: String,
+        matches: Vec<String>,
+    },
+    #[error("More than one device is connected, and prompting is disabled: {}", devices.join(", "))]
+    SelectionRequired { devices: Vec<String> },
 }
 
 #[derive(Debug)]
@@ -33,6 +42,11 @@ impl<T: Display> Display for PromptError<T> {
                 "Failed to prompt for {} device: No connected devices detected",
                 self.name
             ),
+            cause @ (PromptErrorCause::NoneMatched(_)
+            | PromptErrorCause::AmbiguousMatch { .. }
+            | PromptErrorCause::SelectionRequired { .. }) => {
+                write!(f, "Failed to select {} device: {}", self.name, cause)
+            }
         }
     }
 }
@@ -52,6 +66,19 @@ impl<T: Debug + Display> Reportable for PromptError<T> {
                 format!("Failed to prompt for {} device", self.name),
                 format!("No connected {} devices detected", self.name),
             ),
+            PromptErrorCause::NoneMatched(_) | PromptErrorCause::AmbiguousMatch { .. } => {
+                Report::error(
+                    format!("Failed to select {} device", self.name),
+                    &self.cause,
+                )
+            }
+            PromptErrorCause::SelectionRequired { .. } => Report::action_request(
+                format!(
+                    "Please specify which {} device to use with `--device`",
+                    self.name
+                ),
+                &self.cause,
+            ),
         }
     }
 }
@@ -72,42 +99,191 @@ impl<T: Debug + Display> PromptError<T> {
     pub fn none_detected(name: &'static str) -> Self {
         Self::new(name, PromptErrorCause::NoneDetected)
     }
+
+    pub fn none_matched(name: &'static str, selector: impl Into<String>) -> Self {
+        Self::new(name, PromptErrorCause::NoneMatched(selector.into()))
+    }
+
+    pub fn ambiguous_match(
+        name: &'static str,
+        selector: impl Into<String>,
+        matches: Vec<String>,
+    ) -> Self {
+        Self::new(
+            name,
+            PromptErrorCause::AmbiguousMatch {
+                selector: selector.into(),
+                matches,
+            },
+        )
+    }
+
+    pub fn selection_required(name: &'static str, devices: Vec<String>) -> Self {
+        Self::new(name, PromptErrorCause::SelectionRequired { devices })
+    }
+}
+
+/// A device that can be picked out of a device list by a user-provided
+/// selector (i.e. from `--device`).
+pub trait Selectable: Display {
+    /// The unique identifier of this device (i.e. the adb serial).
+    fn id(&self) -> &str;
+
+    /// Whether this device is a candidate for the given selector.
+    fn matches(&self, selector: &str) -> bool;
+}
+
+/// Describes a device along with its identifier, since the display name alone
+/// is often not enough to tell devices apart.
+pub fn describe(device: &impl Selectable) -> String {
+    format!("{} [{}]", device, device.id())
+}
+
+/// Picks the single device matching `selector`. An exact match on the device
+/// identifier always wins, so that a serial can be used to disambiguate
+/// devices that share a name or model. An empty selector matches nothing.
+pub fn select<D: Selectable, T: Debug + Display>(
+    name: &'static str,
+    devices: impl IntoIterator<Item = D>,
+    selector: &str,
+) -> Result<D, PromptError<T>> {
+    let mut matches = devices
+        .into_iter()
+        .filter(|device| !selector.is_empty() && device.matches(selector))
+        .collect::<Vec<_>>();
+    if matches.len() > 1 {
+        if let Some(index) = matches.iter().position(|device| device.id() == selector) {
+            return Ok(matches.swap_remove(index));
+        }
+    }
+    match matches.len() {
+        0 => Err(PromptError::none_matched(name, selector)),
+        1 => Ok(matches.remove(0)),
+        _ => Err(PromptError::ambiguous_match(
+            name,
+            selector,
+            matches.iter().map(describe).collect(),
+        )),
+    }
 }
 
 #[macro_export]
 macro_rules! define_device_prompt {
+    (@select $func:path, $name:ident, $env:expr, $selector:expr, $non_interactive:expr) => {{
+        let device_list = $func($env).map_err(|cause| {
+            $crate::device::PromptError::detection_failed(stringify!($name), cause)
+        })?;
+        if device_list.is_empty() {
+            return Err($crate::device::PromptError::none_detected(stringify!(
+                $name
+            )));
+        }
+        let device = if let Some(selector) = $selector {
+            $crate::device::select(stringify!($name), device_list, &selector)?
+        } else if device_list.len() > 1 {
+            if $non_interactive {
+                return Err($crate::device::PromptError::selection_required(
+                    stringify!($name),
+                    device_list.iter().map($crate::device::describe).collect(),
+                ));
+            }
+            let index = prompt::list(
+                concat!("Detected ", stringify!($name), " devices"),
+                device_list.iter(),
+                "device",
+                None,
+                "Device",
+            )
+            .map_err(|cause| {
+                $crate::device::PromptError::prompt_failed(stringify!($name), cause)
+            })?;
+            device_list.into_iter().nth(index).unwrap()
+        } else {
+            device_list.into_iter().next().unwrap()
+        };
+        println!(
+            "Detected connected device: {} with target {:?}",
+            device,
+            device.target().triple,
+        );
+        Ok(device)
+    }};
     ($func:path, $e:ty, $name:ident) => {
         fn device_prompt<'a>(env: &'_ Env) -> Result<Device<'a>, $crate::device::PromptError<$e>> {
-            let device_list = $func(env).map_err(|cause| {
-                $crate::device::PromptError::detection_failed(stringify!($name), cause)
-            })?;
-            if device_list.len() > 0 {
-                let index = if device_list.len() > 1 {
-                    prompt::list(
-                        concat!("Detected ", stringify!($name), " devices"),
-                        device_list.iter(),
-                        "device",
-                        None,
-                        "Device",
-                    )
-                    .map_err(|cause| {
-                        $crate::device::PromptError::prompt_failed(stringify!($name), cause)
-                    })?
-                } else {
-                    0
-                };
-                let device = device_list.into_iter().nth(index).unwrap();
-                println!(
-                    "Detected connected device: {} with target {:?}",
-                    device,
-                    device.target().triple,
-                );
-                Ok(device)
-            } else {
-                Err($crate::device::PromptError::none_detected(stringify!(
-                    $name
-                )))
-            }
+            $crate::define_device_prompt!(@select $func, $name, env, None::<String>, false)
         }
     };
+    ($func:path, $e:ty, $name:ident, $selector_var:literal) => {
+        fn device_prompt<'a>(
+            env: &'_ Env,
+            selector: Option<&str>,
+            non_interactive: bool,
+        ) -> Result<Device<'a>, $crate::device::PromptError<$e>> {
+            let selector = selector
+                .map(ToOwned::to_owned)
+                .or_else(|| std::env::var($selector_var).ok())
+                .filter(|selector| !selector.is_empty());
+            $crate::define_device_prompt!(@select $func, $name, env, selector, non_interactive)
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[derive(Debug, PartialEq)]
+    struct TestDevice {
+        id: &'static str,
+        name: &'static str,
+    }
+
+    impl Display for TestDevice {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.name)
+        }
+    }
+
+    impl Selectable for TestDevice {
+        fn id(&self) -> &str {
+            self.id
+        }
+
+        fn matches(&self, selector: &str) -> bool {
+            self.id.contains(selector) || self.name.contains(selector)
+        }
+    }
+
+    fn devices() -> Vec<TestDevice> {
+        vec![
+            TestDevice {
+                id: "emulator-5554",
+                name: "Pixel 7",
+            },
+            TestDevice {
+                id: "emulator-55540",
+                name: "Pixel 7 Pro",
+            },
+            TestDevice {
+                id: "0a388e93",
+                name: "Nexus 7",
+            },
+        ]
+    }
+
+    #[rstest(selector, expected,
+        case("emulator-5554", Ok("emulator-5554")),
+        case("0a388e93", Ok("0a388e93")),
+        case("Pro", Ok("emulator-55540")),
+        case("Pixel", Err("More than one connected device matched \"Pixel\": Pixel 7 [emulator-5554], Pixel 7 Pro [emulator-55540]")),
+        case("Galaxy", Err("No connected device matched \"Galaxy\"")),
+        case("", Err("No connected device matched \"\"")),
+    )]
+    fn test_select(selector: &str, expected: Result<&str, &str>) {
+        let actual = select::<_, String>("Test", devices(), selector)
+            .map(|device| device.id)
+            .map_err(|err| err.cause.to_string());
+        assert_eq!(actual, expected.map_err(ToOwned::to_owned));
+    }
 }
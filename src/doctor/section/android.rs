@@ -1,28 +1,79 @@
-use super::Section;
-use crate::{android, doctor::Unrecoverable, os::Env, util};
+use super::{Item, Section};
+use crate::{android, config, doctor::Unrecoverable, os::Env, util};
+
+/// The config of the project in the current directory, if there is one.
+fn project_config() -> Option<Result<config::Config, config::FromRawError>> {
+    let Ok(Some((root_dir, raw))) = config::Raw::load(".") else {
+        return None;
+    };
+    Some(config::Config::from_raw(root_dir, raw))
+}
+
+/// Warns about SDK Platforms the project compiles or targets against that
+/// aren't installed.
+fn check_platforms(
+    android_env: &android::env::Env,
+    config: Option<&Result<config::Config, config::FromRawError>>,
+) -> Vec<Item> {
+    match config {
+        None => Vec::new(),
+        Some(Ok(config)) => {
+            let mut api_levels = vec![
+                config.android().compile_sdk_version(),
+                config.android().target_sdk_version(),
+            ];
+            api_levels.dedup();
+            api_levels
+                .into_iter()
+                .filter(|api_level| !android_env.platform_installed(*api_level))
+                .map(|api_level| {
+                    Item::warning(format!(
+                        "SDK Platform {} isn't installed; install it with `sdkmanager \"platforms;android-{}\"`",
+                        api_level, api_level
+                    ))
+                })
+                .collect()
+        }
+        Some(Err(err)) => vec![Item::failure(format!("Project config invalid: {}", err))],
+    }
+}
 
 pub fn check(env: &Env) -> Result<Section, Unrecoverable> {
     let section = Section::new("Android developer tools");
-    Ok(match android::env::Env::from_env(env.clone()) {
-        Ok(android_env) => section
-            // It'd be a bit too inconvenient to use `map` here, since we need
-            // to use `?` within the closures...
-            .with_item(match android_env.sdk_version() {
-                Ok(sdk_version) => Ok(format!(
-                    "SDK v{} installed at {:?}",
-                    sdk_version,
-                    util::contract_home(android_env.android_home())?,
-                )),
-                Err(err) => Err(format!("Failed to get SDK version: {}", err)),
-            })
-            .with_item(match android_env.ndk.version() {
-                Ok(ndk_version) => Ok(format!(
-                    "NDK v{} installed at {:?}",
-                    ndk_version,
-                    util::contract_home(android_env.ndk.home())?,
-                )),
-                Err(err) => Err(format!("Failed to get NDK version: {}", err)),
-            }),
-        Err(err) => section.with_failure(err),
-    })
+    let config = project_config();
+    let android_config = config
+        .as_ref()
+        .and_then(|config| config.as_ref().ok())
+        .map(|config| config.android());
+    Ok(
+        match android::env::Env::from_env(env.clone(), android_config) {
+            Ok(android_env) => section
+                // It'd be a bit too inconvenient to use `map` here, since we need
+                // to use `?` within the closures...
+                .with_item(match android_env.sdk_version() {
+                    Ok(sdk_version) => Ok(format!(
+                        "SDK v{} installed at {:?} (found {})",
+                        sdk_version,
+                        util::contract_home(android_env.android_home())?,
+                        android_env.sdk_source(),
+                    )),
+                    Err(err) => Err(format!(
+                        "Failed to get version of SDK at {:?} (found {}): {}",
+                        util::contract_home(android_env.android_home())?,
+                        android_env.sdk_source(),
+                        err
+                    )),
+                })
+                .with_item(match android_env.ndk.version() {
+                    Ok(ndk_version) => Ok(format!(
+                        "NDK v{} installed at {:?}",
+                        ndk_version,
+                        util::contract_home(android_env.ndk.home())?,
+                    )),
+                    Err(err) => Err(format!("Failed to get NDK version: {}", err)),
+                })
+                .with_items(check_platforms(&android_env, config.as_ref())),
+            Err(err) => section.with_failure(err),
+        },
+    )
 }
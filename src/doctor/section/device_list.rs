@@ -15,7 +15,7 @@ pub fn check(env: &Env) -> Section {
         }
     };
 
-    let section = if let Ok(android_env) = android::env::Env::from_env(env.clone()) {
+    let section = if let Ok(android_env) = android::env::Env::from_env(env.clone(), None) {
         match adb::device_list(&android_env) {
             Ok(list) => section.with_victories(list),
             Err(err) => section.with_failure(format!("Failed to get Android device list: {}", err)),
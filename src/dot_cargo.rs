@@ -65,11 +65,12 @@ impl DotCargoBuild {
 pub struct DotCargoTarget {
     pub linker: Option<String>,
     pub rustflags: Vec<String>,
+    pub runner: Option<String>,
 }
 
 impl DotCargoTarget {
     pub fn is_empty(&self) -> bool {
-        self.linker.is_none() && self.rustflags.is_empty()
+        self.linker.is_none() && self.rustflags.is_empty() && self.runner.is_none()
     }
 }
 
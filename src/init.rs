@@ -187,7 +187,7 @@ pub fn exec(
 
     // Generate Android Studio project
     if metadata.android().supported() {
-        match android::env::Env::new() {
+        match android::env::Env::new(Some(config.android())) {
             Ok(env) => android::project::gen(
                 config.android(),
                 metadata.android(),
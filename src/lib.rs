@@ -27,6 +27,9 @@ pub static NAME: &str = "mobile";
 trait DuctExpressionExt {
     fn vars(self, vars: impl IntoIterator<Item = (impl AsRef<OsStr>, impl AsRef<OsStr>)>) -> Self;
     fn run_and_detach(self) -> Result<(), std::io::Error>;
+    // Starts the command in a session of its own, so it outlives us without
+    // being interrupted along with us, while we can still wait on it.
+    fn start_in_new_session(self) -> Result<duct::Handle, std::io::Error>;
     // Sets the stdin, stdout and stderr to properly
     // show the command output in a Node.js wrapper (napi-rs).
     fn dup_stdio(&self) -> Self;
@@ -88,6 +91,34 @@ impl DuctExpressionExt for duct::Expression {
         Ok(())
     }
 
+    fn start_in_new_session(self) -> Result<duct::Handle, std::io::Error> {
+        self.before_spawn(|cmd| {
+            #[cfg(unix)]
+            #[allow(unsafe_code)]
+            unsafe {
+                use std::os::unix::process::CommandExt as _;
+
+                cmd.pre_exec(|| {
+                    if libc::setsid() == -1 {
+                        Err(std::io::Error::last_os_error())
+                    } else {
+                        Ok(())
+                    }
+                });
+            }
+            #[cfg(windows)]
+            {
+                use std::os::windows::process::CommandExt;
+                const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+                const CREATE_NO_WINDOW: u32 = 0x08000000;
+                cmd.creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW);
+            }
+
+            Ok(())
+        })
+        .start()
+    }
+
     fn dup_stdio(&self) -> Self {
         self.stdin_file(os_pipe::dup_stdin().unwrap())
             .stdout_file(os_pipe::dup_stdout().unwrap())
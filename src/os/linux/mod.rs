@@ -181,5 +181,7 @@ pub mod consts {
     pub const AR: &str = "ar";
     pub const LD: &str = "ld";
     pub const READELF: &str = "readelf";
-    pub const NDK_STACK: &str = "ndk-stack";
+    pub const LLVM_SYMBOLIZER: &str = "llvm-symbolizer";
+    pub const LLVM_STRIP: &str = "llvm-strip";
+    pub const LLVM_NM: &str = "llvm-nm";
 }
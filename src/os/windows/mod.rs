@@ -279,5 +279,7 @@ pub mod consts {
     pub const LD: &str = "ld.exe";
     pub const AR: &str = "ar.exe";
     pub const READELF: &str = "readelf.exe";
-    pub const NDK_STACK: &str = "ndk-stack.cmd";
+    pub const LLVM_SYMBOLIZER: &str = "llvm-symbolizer.exe";
+    pub const LLVM_STRIP: &str = "llvm-strip.exe";
+    pub const LLVM_NM: &str = "llvm-nm.exe";
 }
@@ -73,6 +73,11 @@ impl<'a> CargoCommand<'a> {
     }
 
     pub fn build(self, env: &impl ExplicitEnv) -> duct::Expression {
+        self.build_without_stdio(env).dup_stdio()
+    }
+
+    /// Like [`Self::build`], but leaves stdio alone so output can be captured.
+    pub fn build_without_stdio(self, env: &impl ExplicitEnv) -> duct::Expression {
         let mut args = vec![self.subcommand.to_owned()];
         if self.verbose {
             args.push("-vv".into());
@@ -117,7 +122,6 @@ impl<'a> CargoCommand<'a> {
         duct::cmd("cargo", args)
             .vars(env.explicit_env())
             .vars(explicit_cargo_env())
-            .dup_stdio()
     }
 }
 
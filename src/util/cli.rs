@@ -141,7 +141,7 @@ mod interface {
     use std::fmt::Debug;
 
     use crate::{opts, util};
-    use once_cell_regex::exports::once_cell::sync::Lazy;
+    use once_cell_regex::exports::{once_cell::sync::Lazy, regex::Regex};
     use structopt::{
         clap::{self, AppSettings},
         StructOpt,
@@ -236,6 +236,48 @@ mod interface {
         pub filter: Option<opts::FilterLevel>,
     }
 
+    #[derive(Clone, Debug, StructOpt)]
+    pub struct LogFilter {
+        #[structopt(
+            long = "tag",
+            help = "Only show logs with this tag (can be repeated)",
+            number_of_values = 1
+        )]
+        pub tags: Vec<String>,
+        #[structopt(
+            long = "grep",
+            help = "Only show logs whose tag or message matches this regex"
+        )]
+        pub grep: Option<Regex>,
+        #[structopt(
+            long = "pid",
+            help = "Only show logs from this process (can be repeated)",
+            number_of_values = 1
+        )]
+        pub pids: Vec<u32>,
+        #[structopt(long = "json", help = "Print logs as newline-delimited JSON")]
+        pub json: bool,
+    }
+
+    #[derive(Clone, Debug, StructOpt)]
+    pub struct Device {
+        #[structopt(
+            long = "device",
+            help = "Device to use, matched against its serial, name or model (falls back to `ANDROID_SERIAL` on Android)"
+        )]
+        pub device: Option<String>,
+    }
+
+    #[derive(Clone, Copy, Debug, StructOpt)]
+    pub struct AllDevices {
+        #[structopt(
+            long = "all-devices",
+            help = "Use every connected device",
+            conflicts_with = "device"
+        )]
+        pub all_devices: bool,
+    }
+
     pub trait Exec: Debug + StructOpt {
         type Report: Reportable;
 